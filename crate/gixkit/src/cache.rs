@@ -0,0 +1,314 @@
+use crate::{Error, Result};
+use gix::bstr::{BStr, BString};
+use gix::index::entry::{stat, Stat};
+use gix::Repository;
+use gix_hash::ObjectId;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+const MAGIC: &[u8; 8] = b"GOTSTAT2";
+
+/// Blob ids of worktree files from earlier runs, kept in `.git/got/status-cache`.
+///
+/// A tracked file whose stat data no longer matches its index entry (edited,
+/// or racily clean) has to be read and hashed to tell whether it changed. The
+/// cache remembers what it hashed to against the file's own stat data, so
+/// later runs skip the read for as long as the file is left alone.
+///
+/// Everything is dropped when the index entries, `info/attributes`, the global
+/// attributes file, the line ending config or any `filter.<driver>` config
+/// changes. A changed `.gitattributes` drops the entries below its directory.
+/// Entries are written back when the last iterator sharing the cache is gone.
+pub(crate) struct StatusCache {
+    path: PathBuf,
+    work_dir: Option<PathBuf>,
+    /// What the entries were computed under
+    key: Vec<u8>,
+    /// Stat data of the `.gitattributes` in each directory leading to an
+    /// entry, `""` for the worktree root; default stat data where there is none
+    attributes: Mutex<HashMap<BString, Stat>>,
+    entries: Mutex<HashMap<BString, (Stat, ObjectId)>>,
+    dirty: AtomicBool,
+}
+
+impl StatusCache {
+    /// The cache for `repo`'s worktree; empty if there is none yet, it can't be
+    /// read, or it was written under a different key. Entries below a
+    /// `.gitattributes` that changed since are left out.
+    pub fn load(repo: &Repository, index: &gix::index::File) -> Self {
+        let path = repo.git_dir().join("got").join("status-cache");
+        let work_dir = repo.workdir().map(Path::to_owned);
+        let key = cache_key(repo, index);
+        let (mut attributes, mut entries) = std::fs::read(&path)
+            .ok()
+            .and_then(|data| decode(&data, &key))
+            .unwrap_or_default();
+        if let Some(ref work_dir) = work_dir {
+            attributes.retain(|dir, stat| attributes_stat(work_dir, dir.as_ref()) == *stat);
+        }
+        entries
+            .retain(|path, _| parent_dirs(path.as_ref()).all(|dir| attributes.contains_key(dir)));
+        Self {
+            path,
+            work_dir,
+            key,
+            attributes: Mutex::new(attributes),
+            entries: Mutex::new(entries),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// The blob id `path` hashed to when its stat data was exactly `stat`
+    pub fn get(&self, path: &BStr, stat: &Stat) -> Option<ObjectId> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(path)
+            .filter(|(cached, _)| cached == stat)
+            .map(|(_, id)| *id)
+    }
+
+    /// Remember a freshly computed blob id. Files modified within the current
+    /// second are left out: another write in the same second could keep the
+    /// same stat data, as with racily clean index entries.
+    pub fn insert(&self, path: &BStr, stat: Stat, id: ObjectId) {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+        if u64::from(stat.mtime.secs) >= now {
+            return;
+        }
+        let Some(ref work_dir) = self.work_dir else {
+            return;
+        };
+        {
+            let mut attributes = self.attributes.lock().unwrap();
+            for dir in parent_dirs(path) {
+                if !attributes.contains_key(dir) {
+                    attributes.insert(dir.to_owned(), attributes_stat(work_dir, dir));
+                }
+            }
+        }
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), (stat, id));
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    fn save(&self) -> Result<()> {
+        let attributes = self.attributes.lock().unwrap();
+        let entries = self.entries.lock().unwrap();
+        let data = encode(&self.key, &attributes, &entries);
+        let dir = self
+            .path
+            .parent()
+            .expect("cache file is inside the git dir");
+        std::fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+        // concurrent runs each write their own file; the last rename wins
+        let tmp = dir.join(format!("status-cache.{}.tmp", std::process::id()));
+        std::fs::write(&tmp, data).map_err(|e| Error::io(&tmp, e))?;
+        std::fs::rename(&tmp, &self.path).map_err(|e| {
+            let _ = std::fs::remove_file(&tmp);
+            Error::io(&self.path, e)
+        })?;
+        Ok(())
+    }
+}
+
+impl Drop for StatusCache {
+    fn drop(&mut self) {
+        if self.dirty.load(Ordering::Relaxed) {
+            // only a cache: failing to write it just means hashing again next time
+            let _ = self.save();
+        }
+    }
+}
+
+/// Everything the cached blob ids depend on besides the files themselves and
+/// the `.gitattributes` in the worktree
+fn cache_key(repo: &Repository, index: &gix::index::File) -> Vec<u8> {
+    let mut key = Vec::new();
+    // paths and ids only: the index is rewritten for its stat data and its
+    // extensions, like the untracked cache, which leave the blob ids as they are
+    key.extend_from_slice(&entries_hash(index).to_be_bytes());
+    push_stat(
+        &mut key,
+        &stat_of(&repo.common_dir().join("info").join("attributes")),
+    );
+    if let Some(path) = attributes_file(repo) {
+        push_bytes(&mut key, path.as_os_str().as_encoded_bytes());
+        push_stat(&mut key, &stat_of(&path));
+    }
+
+    let config = repo.config_snapshot();
+    for name in ["core.autocrlf", "core.eol"] {
+        if let Some(value) = config.string(name) {
+            key.extend_from_slice(name.as_bytes());
+            key.extend_from_slice(&value);
+        }
+        key.push(0);
+    }
+    // clean filter commands, `required` and the like, whatever the driver
+    for section in config
+        .plumbing()
+        .sections_by_name("filter")
+        .into_iter()
+        .flatten()
+    {
+        push_bytes(&mut key, &section.to_bstring());
+    }
+    key
+}
+
+/// Hash of the path and blob id of every index entry; ids matter for `text=auto`
+/// and for the `.gitattributes` read from the index
+fn entries_hash(index: &gix::index::File) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for entry in index.entries() {
+        entry.path(index).hash(&mut hasher);
+        entry.id.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// `core.attributesFile`, or git's default of `$XDG_CONFIG_HOME/git/attributes`
+fn attributes_file(repo: &Repository) -> Option<PathBuf> {
+    let config = repo.config_snapshot();
+    if let Some(path) = config.trusted_path("core.attributesFile").transpose() {
+        return path.ok();
+    }
+    gix::path::env::xdg_config("attributes", &mut |name| std::env::var_os(name))
+}
+
+fn stat_of(path: &Path) -> Stat {
+    gix::index::fs::Metadata::from_path_no_follow(path)
+        .ok()
+        .and_then(|meta| Stat::from_fs(&meta).ok())
+        .unwrap_or_default()
+}
+
+/// Stat data of the `.gitattributes` in `dir`, a directory in the worktree
+fn attributes_stat(work_dir: &Path, dir: &BStr) -> Stat {
+    stat_of(&crate::repo::worktree_path(work_dir, dir).join(".gitattributes"))
+}
+
+/// `""` for the worktree root, then each directory leading to `path`
+fn parent_dirs(path: &BStr) -> impl Iterator<Item = &BStr> {
+    std::iter::once(BStr::new("")).chain(
+        path.iter()
+            .enumerate()
+            .filter(|(_, &b)| b == b'/')
+            .map(|(end, _)| BStr::new(&path[..end])),
+    )
+}
+
+fn push_stat(out: &mut Vec<u8>, stat: &Stat) {
+    for n in [
+        stat.mtime.secs,
+        stat.mtime.nsecs,
+        stat.ctime.secs,
+        stat.ctime.nsecs,
+        stat.dev,
+        stat.ino,
+        stat.uid,
+        stat.gid,
+        stat.size,
+    ] {
+        out.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+fn push_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+type Decoded = (HashMap<BString, Stat>, HashMap<BString, (Stat, ObjectId)>);
+
+/// `MAGIC`, the key, the `.gitattributes` stat data as a count and then
+/// directory and stat data for each, then `path`, stat data and blob id per entry
+fn encode(
+    key: &[u8],
+    attributes: &HashMap<BString, Stat>,
+    entries: &HashMap<BString, (Stat, ObjectId)>,
+) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    push_bytes(&mut out, key);
+    out.extend_from_slice(&(attributes.len() as u32).to_be_bytes());
+    for (dir, stat) in attributes {
+        push_bytes(&mut out, dir);
+        push_stat(&mut out, stat);
+    }
+    for (path, (stat, id)) in entries {
+        push_bytes(&mut out, path);
+        push_stat(&mut out, stat);
+        push_bytes(&mut out, id.as_bytes());
+    }
+    out
+}
+
+/// `None` for a cache written under another key, or one that doesn't parse
+fn decode(data: &[u8], key: &[u8]) -> Option<Decoded> {
+    let mut reader = Reader(data.strip_prefix(MAGIC)?);
+    if reader.bytes()? != key {
+        return None;
+    }
+    let mut attributes = HashMap::new();
+    for _ in 0..reader.u32()? {
+        let dir = BString::from(reader.bytes()?);
+        attributes.insert(dir, reader.stat()?);
+    }
+    let mut entries = HashMap::new();
+    while !reader.0.is_empty() {
+        let path = BString::from(reader.bytes()?);
+        let stat = reader.stat()?;
+        let id = ObjectId::try_from(reader.bytes()?).ok()?;
+        entries.insert(path, (stat, id));
+    }
+    Some((attributes, entries))
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn stat(&mut self) -> Option<Stat> {
+        let mut n = || self.u32();
+        Some(Stat {
+            mtime: stat::Time {
+                secs: n()?,
+                nsecs: n()?,
+            },
+            ctime: stat::Time {
+                secs: n()?,
+                nsecs: n()?,
+            },
+            dev: n()?,
+            ino: n()?,
+            uid: n()?,
+            gid: n()?,
+            size: n()?,
+        })
+    }
+}
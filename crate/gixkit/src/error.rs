@@ -0,0 +1,112 @@
+use gix_hash::ObjectId;
+use std::path::PathBuf;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Any error, kept as the cause of one of ours
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// What went wrong in gixkit, for callers to tell failures apart without
+/// matching on messages. The cause, when there is one, is the error's
+/// `source()`.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    /// Neither `path` nor any of its parents holds a repository
+    #[error("Not a git repository (or any of the parent directories): '{}'", path.display())]
+    NotARepository {
+        path: PathBuf,
+        #[source]
+        source: Option<BoxError>,
+    },
+    /// There is a repository at `path`, but it can't be opened: unreadable
+    /// config, an object format gix doesn't support, and the like
+    #[error("Failed to open git repository '{}'", path.display())]
+    Open {
+        path: PathBuf,
+        #[source]
+        source: gix::Error,
+    },
+    /// Worktree status was asked of a repository without a worktree
+    #[error("Repository has no working directory; open one of its worktrees (list_worktrees)")]
+    BareRepository,
+    /// The index file exists but can't be read
+    #[error("Failed to read the index")]
+    IndexCorrupt(#[source] gix::Error),
+    /// Reading or writing `path` failed
+    #[error("Failed to access '{}'", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// An object the index or a tree refers to isn't in the object database
+    #[error("Object {id} is missing")]
+    ObjectMissing { id: ObjectId },
+    /// `rev` doesn't name a revision
+    #[error("Failed to resolve '{rev}'")]
+    RevisionNotFound {
+        rev: String,
+        #[source]
+        source: gix::Error,
+    },
+    /// `rev` is a range, where a single revision or `a...b` was expected
+    #[error("'{rev}' is a range; expected a single revision or 'a...b' for a merge base")]
+    RevisionRange { rev: String },
+    /// `rev` names an object that isn't a commit or tree
+    #[error("'{rev}' has no tree")]
+    NotATree {
+        rev: String,
+        #[source]
+        source: BoxError,
+    },
+    /// `a...b` was asked for, but the histories never meet
+    #[error("No merge base between {a} and {b}")]
+    NoMergeBase { a: ObjectId, b: ObjectId },
+    /// Builder options that can't be honored together
+    #[error("Unsupported combination of ignored and untracked-files arguments")]
+    UnsupportedOptions,
+    /// The repository nested at `path` couldn't be opened or iterated
+    #[error("Failed to open nested repository '{}'", path.display())]
+    NestedRepository {
+        path: PathBuf,
+        #[source]
+        source: Box<Error>,
+    },
+    /// Any other failure from gix: config, attributes, pathspecs, filters,
+    /// history traversal
+    #[error(transparent)]
+    Git(BoxError),
+}
+
+impl Error {
+    pub(crate) fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        Error::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    pub(crate) fn git(source: impl Into<BoxError>) -> Self {
+        Error::Git(source.into())
+    }
+
+    /// For a failed lookup of object `id`: [`Error::ObjectMissing`] if it isn't
+    /// there, the lookup's own error otherwise
+    pub(crate) fn object(id: impl Into<ObjectId>) -> impl FnOnce(gix::Error) -> Self {
+        let id = id.into();
+        move |e| {
+            if e.classify().is_not_found() {
+                Error::ObjectMissing { id }
+            } else {
+                Error::git(e)
+            }
+        }
+    }
+}
+
+impl From<gix::Error> for Error {
+    fn from(e: gix::Error) -> Self {
+        Error::git(e)
+    }
+}
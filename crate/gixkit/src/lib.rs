@@ -1,7 +1,18 @@
+mod cache;
+pub mod error;
+mod parallel;
+pub mod quote;
+pub mod rename;
 pub mod repo;
 pub mod repo_iter;
 pub mod types;
+mod worktree;
 
+pub use error::{Error, Result};
+pub use gix::bstr::{BStr, BString};
+pub use gix::ThreadSafeRepository;
+pub use quote::{cwd_prefix, quote_non_ascii, quote_path, relative_path};
+pub use rename::DEFAULT_RENAME_THRESHOLD;
 pub use repo::*;
 pub use repo_iter::{IterMode, RepoIter, RepoIterBuilder};
 pub use types::*;
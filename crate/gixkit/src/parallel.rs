@@ -0,0 +1,163 @@
+use crate::Result;
+use gix::{Repository, ThreadSafeRepository};
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use crate::repo_iter::{IterOptions, RepoIter, TrackedItem};
+use crate::types::FileStatus;
+
+/// Fewest tracked items worth handing to a worker at once
+const MIN_CHUNK: usize = 64;
+
+pub(crate) enum Task {
+    /// A run of tracked items, numbered so results can be put back in order
+    Tracked(usize, Vec<TrackedItem>),
+    /// The walk for untracked and ignored paths
+    Untracked,
+}
+
+/// Position of a tracked result in sequential order: chunk number, then
+/// position within the chunk. Untracked results have none and sort by path.
+type Order = Option<(usize, usize)>;
+
+/// Shared work list, filled before the workers start
+#[derive(Default)]
+pub(crate) struct TaskQueue {
+    tasks: Mutex<VecDeque<Task>>,
+}
+
+impl TaskQueue {
+    fn push(&self, task: Task) {
+        self.tasks.lock().unwrap().push_back(task);
+    }
+
+    fn pop(&self) -> Option<Task> {
+        self.tasks.lock().unwrap().pop_front()
+    }
+
+    /// Nobody is listening anymore; let every worker wind down
+    fn close(&self) {
+        self.tasks.lock().unwrap().clear();
+    }
+}
+
+/// The receiving end of a worker pool computing statuses for a `RepoIter`
+pub(crate) struct Parallel {
+    results: Receiver<(Order, Result<FileStatus>)>,
+    ordered: bool,
+}
+
+impl Parallel {
+    pub fn spawn(
+        repo: &Repository,
+        options: IterOptions,
+        threads: usize,
+        tracked: Vec<TrackedItem>,
+        untracked: bool,
+    ) -> Self {
+        let threads = match threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        let queue = Arc::new(TaskQueue::default());
+
+        // the walk is one task, and likely the longest: it goes first
+        if untracked {
+            queue.push(Task::Untracked);
+        }
+        let chunk_size = (tracked.len() / (threads * 4)).max(MIN_CHUNK);
+        let mut tracked = tracked.into_iter();
+        for chunk in 0.. {
+            let items: Vec<_> = tracked.by_ref().take(chunk_size).collect();
+            if items.is_empty() {
+                break;
+            }
+            queue.push(Task::Tracked(chunk, items));
+        }
+
+        let ordered = options.ordered;
+        let sync_repo = repo.clone().into_sync();
+        let (sender, results) = mpsc::channel();
+        for _ in 0..threads {
+            let repo = sync_repo.clone();
+            let options = options.clone();
+            let queue = Arc::clone(&queue);
+            let sender = sender.clone();
+            std::thread::spawn(move || work(repo, options, queue, sender));
+        }
+
+        Self { results, ordered }
+    }
+
+    pub fn is_ordered(&self) -> bool {
+        self.ordered
+    }
+
+    /// Next result as soon as any worker has one
+    pub fn recv(&self) -> Option<Result<FileStatus>> {
+        self.results.recv().ok().map(|(_, result)| result)
+    }
+
+    /// Every result once all workers are done: tracked paths in index order,
+    /// then untracked paths sorted, ignored ones last, then errors
+    pub fn collect(self) -> Vec<Result<FileStatus>> {
+        let mut tracked = Vec::new();
+        let mut untracked = Vec::new();
+        let mut errors = Vec::new();
+        for (order, result) in self.results {
+            match (order, result) {
+                (_, Err(e)) => errors.push(Err(e)),
+                (Some(order), Ok(status)) => tracked.push((order, status)),
+                (None, Ok(status)) => untracked.push(status),
+            }
+        }
+        if self.ordered {
+            tracked.sort_by_key(|(order, _)| *order);
+            crate::repo_iter::sort_untracked(&mut untracked);
+        }
+        tracked
+            .into_iter()
+            .map(|(_, status)| Ok(status))
+            .chain(untracked.into_iter().map(Ok))
+            .chain(errors)
+            .collect()
+    }
+}
+
+fn work(
+    repo: ThreadSafeRepository,
+    options: IterOptions,
+    queue: Arc<TaskQueue>,
+    sender: Sender<(Order, Result<FileStatus>)>,
+) {
+    #[allow(clippy::arc_with_non_send_sync)]
+    let repo = Arc::new(repo.to_thread_local());
+    let mut worker = match RepoIter::worker(repo, &options) {
+        Ok(worker) => worker,
+        Err(e) => {
+            let _ = sender.send((None, Err(e)));
+            return;
+        }
+    };
+
+    while let Some(task) = queue.pop() {
+        let sent = match task {
+            Task::Tracked(chunk, items) => worker
+                .work_tracked(items)
+                .enumerate()
+                .all(|(n, result)| sender.send((Some((chunk, n)), result)).is_ok()),
+            Task::Untracked => worker
+                .work_untracked()
+                .all(|result| sender.send((None, result)).is_ok()),
+        };
+        if !sent {
+            queue.close();
+        }
+    }
+    // let go of the shared status cache before the results channel closes, so
+    // the iterator on the receiving end is the one left to write it out
+    drop(worker);
+    drop(options);
+    drop(sender);
+}
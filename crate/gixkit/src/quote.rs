@@ -0,0 +1,197 @@
+use gix::bstr::{BStr, BString, ByteVec};
+use gix::Repository;
+
+/// Quote `path` the way `git status` prints it: in double quotes with C escapes
+/// when it contains control characters, `"` or `\`, or, with `quote_non_ascii`,
+/// bytes above 0x7f. Any other path comes back as is.
+pub fn quote_path(path: &BStr, quote_non_ascii: bool) -> BString {
+    let needs_quoting =
+        |b: u8| b < 0x20 || b == b'"' || b == b'\\' || b == 0x7f || quote_non_ascii && b >= 0x80;
+    if !path.iter().any(|&b| needs_quoting(b)) {
+        return path.to_owned();
+    }
+
+    let mut quoted = BString::from("\"");
+    for &b in path.iter() {
+        match b {
+            0x07 => quoted.push_str("\\a"),
+            0x08 => quoted.push_str("\\b"),
+            b'\t' => quoted.push_str("\\t"),
+            b'\n' => quoted.push_str("\\n"),
+            0x0b => quoted.push_str("\\v"),
+            0x0c => quoted.push_str("\\f"),
+            b'\r' => quoted.push_str("\\r"),
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            b if needs_quoting(b) => quoted.push_str(format!("\\{:03o}", b)),
+            b => quoted.push_byte(b),
+        }
+    }
+    quoted.push_byte(b'"');
+    quoted
+}
+
+/// `core.quotePath`, on unless set to false: whether non-ASCII bytes get octal escapes
+pub fn quote_non_ascii(repo: &Repository) -> bool {
+    repo.config_snapshot()
+        .boolean("core.quotePath")
+        .unwrap_or(true)
+}
+
+/// Where the current directory sits in the worktree, as a `/`-terminated
+/// repository path (`a/b/`), for [`relative_path`]. `None` at the top level,
+/// outside the worktree, or with `status.relativePaths` set to false.
+pub fn cwd_prefix(repo: &Repository) -> Option<BString> {
+    if !repo
+        .config_snapshot()
+        .boolean("status.relativePaths")
+        .unwrap_or(true)
+    {
+        return None;
+    }
+    let prefix = repo.prefix().ok()??;
+    let mut prefix =
+        gix::path::to_unix_separators_on_windows(gix::path::into_bstr(prefix).ok()?).into_owned();
+    if prefix.is_empty() {
+        return None;
+    }
+    prefix.push_byte(b'/');
+    Some(prefix)
+}
+
+/// A repository path as seen from the `/`-terminated `prefix`, the way
+/// `git status --short` prints it from a subdirectory: `b/f` and `../top` from
+/// `a/`, and `./` for the prefix directory itself
+pub fn relative_path(path: &BStr, prefix: &BStr) -> BString {
+    let mut rest: &[u8] = path;
+    let mut prefix: &[u8] = prefix;
+    while let Some(slash) = prefix.iter().position(|&b| b == b'/') {
+        let component = &prefix[..=slash];
+        if !rest.starts_with(component) {
+            break;
+        }
+        rest = &rest[component.len()..];
+        prefix = &prefix[component.len()..];
+    }
+
+    let mut relative = BString::default();
+    for _ in prefix.iter().filter(|&&b| b == b'/') {
+        relative.push_str("../");
+    }
+    relative.push_str(rest);
+    if relative.is_empty() {
+        relative.push_str("./");
+    }
+    relative
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::process::Command;
+
+    fn quoted(path: &[u8], quote_non_ascii: bool) -> BString {
+        quote_path(path.into(), quote_non_ascii)
+    }
+
+    fn relative(path: &str, prefix: &str) -> BString {
+        relative_path(path.into(), prefix.into())
+    }
+
+    /// A repository with the directory `a/b`, and `config` set
+    fn repo_with_config(config: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(args)
+                .current_dir(dir.path())
+                .env("GIT_CONFIG_NOSYSTEM", "1")
+                .env("GIT_CONFIG_GLOBAL", "/dev/null")
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "-q"]);
+        for (key, value) in config {
+            git(&["config", key, value]);
+        }
+        dir
+    }
+
+    /// Open `dir` with the current directory at `cwd`, which the repository
+    /// takes its prefix from
+    fn open_from(dir: &Path, cwd: &Path) -> Repository {
+        let previous = std::env::current_dir().unwrap();
+        std::env::set_current_dir(cwd).unwrap();
+        let repo = gix::open(dir);
+        std::env::set_current_dir(previous).unwrap();
+        repo.unwrap()
+    }
+
+    #[test]
+    fn plain_paths_are_left_alone() {
+        assert_eq!(quoted(b"dir/file name.txt", true), "dir/file name.txt");
+    }
+
+    #[test]
+    fn control_characters_get_c_escapes() {
+        assert_eq!(
+            quoted(b"a\x07\x08\t\n\x0b\x0c\rb", false),
+            r#""a\a\b\t\n\v\f\rb""#
+        );
+        assert_eq!(quoted(b"a\x01\x1b\x7fb", false), r#""a\001\033\177b""#);
+    }
+
+    #[test]
+    fn double_quotes_and_backslashes_are_escaped() {
+        assert_eq!(quoted(br#"say "hi""#, false), r#""say \"hi\"""#);
+        assert_eq!(quoted(br"back\slash", false), r#""back\\slash""#);
+    }
+
+    #[test]
+    fn non_ascii_bytes_are_escaped_only_with_quote_path() {
+        assert_eq!(quoted("café".as_bytes(), true), r#""caf\303\251""#);
+        assert_eq!(quoted("café".as_bytes(), false), "café");
+        // not UTF-8 at all
+        assert_eq!(quoted(b"f\xffe", true), r#""f\377e""#);
+        assert_eq!(quoted(b"f\xffe", false), &b"f\xffe"[..]);
+        // core.quotePath off still quotes control characters
+        assert_eq!(quoted(b"f\xff\te", false), &b"\"f\xff\\te\""[..]);
+    }
+
+    #[test]
+    fn quote_non_ascii_follows_core_quote_path() {
+        let dir = repo_with_config(&[]);
+        assert!(quote_non_ascii(&gix::open(dir.path()).unwrap()));
+        let dir = repo_with_config(&[("core.quotePath", "false")]);
+        assert!(!quote_non_ascii(&gix::open(dir.path()).unwrap()));
+    }
+
+    #[test]
+    fn relative_paths_climb_out_of_the_prefix() {
+        assert_eq!(relative("a/b/f", "a/"), "b/f");
+        assert_eq!(relative("top", "a/"), "../top");
+        assert_eq!(relative("a/x", "a/b/"), "../x");
+        assert_eq!(relative("c/d", "a/b/"), "../../c/d");
+        // a shared start that isn't a whole component
+        assert_eq!(relative("ab/f", "a/"), "../ab/f");
+        assert_eq!(relative("a/", "a/"), "./");
+        assert_eq!(relative("a/b/", "a/"), "b/");
+    }
+
+    #[test]
+    fn cwd_prefix_is_where_the_repository_was_opened_from() {
+        let dir = repo_with_config(&[]);
+        let path = dir.path().canonicalize().unwrap();
+        let repo = open_from(&path, &path.join("a/b"));
+        assert_eq!(cwd_prefix(&repo), Some("a/b/".into()));
+        assert_eq!(cwd_prefix(&open_from(&path, &path)), None);
+        assert_eq!(cwd_prefix(&open_from(&path, Path::new("/"))), None);
+
+        let dir = repo_with_config(&[("status.relativePaths", "false")]);
+        let path = dir.path().canonicalize().unwrap();
+        assert_eq!(cwd_prefix(&open_from(&path, &path.join("a/b"))), None);
+    }
+}
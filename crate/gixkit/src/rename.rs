@@ -0,0 +1,379 @@
+use crate::{Error, Result};
+use gix::bstr::{BString, ByteSlice, ByteVec};
+use gix::index::entry::Flags;
+use gix::Repository;
+use gix_hash::ObjectId;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+use crate::types::{FileStatus, RenameDetection, StatusChar};
+use crate::worktree::WorktreeHasher;
+
+/// git's default `-M` similarity, in percent
+pub const DEFAULT_RENAME_THRESHOLD: u8 = 50;
+
+/// git's default `diff.renameLimit`
+const DEFAULT_RENAME_LIMIT: usize = 1000;
+
+/// Rename and copy settings, resolved against `status.renames` / `diff.renames`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct RenameOptions {
+    pub detection: RenameDetection,
+    pub threshold: u8,
+    pub limit: usize,
+    /// Worktree files larger than this are never read, not even to hash them
+    pub max_size: Option<u64>,
+    /// Content larger than this is never loaded for inexact matching:
+    /// `max_size`, or else `core.bigFileThreshold`
+    pub max_load_size: u64,
+}
+
+impl RenameOptions {
+    pub fn resolve(
+        repo: &Repository,
+        detection: Option<RenameDetection>,
+        threshold: Option<u8>,
+        max_size: Option<u64>,
+    ) -> Result<Self> {
+        let config = repo.config_snapshot();
+        let detection = detection.unwrap_or_else(|| {
+            config
+                .string("status.renames")
+                .or_else(|| config.string("diff.renames"))
+                .map_or(RenameDetection::Renames, |value| {
+                    parse_detection(value.to_string().as_str())
+                })
+        });
+        let limit = config
+            .integer("status.renameLimit")
+            .or_else(|| config.integer("diff.renameLimit"))
+            .and_then(|limit| usize::try_from(limit).ok())
+            .unwrap_or(DEFAULT_RENAME_LIMIT);
+
+        let max_load_size = match max_size {
+            Some(max) => max,
+            None => repo.big_file_threshold()?,
+        };
+
+        Ok(Self {
+            detection,
+            threshold: threshold.unwrap_or(DEFAULT_RENAME_THRESHOLD).min(100),
+            limit,
+            max_size,
+            max_load_size,
+        })
+    }
+}
+
+fn parse_detection(value: &str) -> RenameDetection {
+    match value.to_ascii_lowercase().as_str() {
+        "copies" | "copy" => RenameDetection::Copies,
+        "false" | "no" | "off" | "0" => RenameDetection::Off,
+        _ => RenameDetection::Renames,
+    }
+}
+
+/// One side of a potential rename or copy: a position in the status list plus
+/// its blob id. Content is only loaded for the sides no exact match pairs up.
+struct Side {
+    pos: usize,
+    id: ObjectId,
+    /// Modified files stay where they are, so they can only ever be a copy source
+    copy_only: bool,
+}
+
+/// Pair deletions with additions (and, for copies, modifications with additions)
+/// in both the staged and the worktree column, rewriting `statuses` in place.
+pub(crate) fn detect(
+    repo: &Repository,
+    work_dir: &Path,
+    base_tree_id: ObjectId,
+    mut statuses: Vec<FileStatus>,
+    options: &RenameOptions,
+) -> Result<Vec<FileStatus>> {
+    if options.detection == RenameDetection::Off {
+        return Ok(statuses);
+    }
+    let copies = options.detection == RenameDetection::Copies;
+    // statuses from inside a submodule or a nested repository (reported as
+    // `dir/`) were already paired by its own iterator
+    let nested_prefixes: Vec<BString> = statuses
+        .iter()
+        .filter_map(|status| {
+            if status.is_gitlink() {
+                let mut prefix = status.path.clone();
+                prefix.push_byte(b'/');
+                Some(prefix)
+            } else {
+                status.path.ends_with(b"/").then(|| status.path.clone())
+            }
+        })
+        .collect();
+    let skip = |status: &FileStatus| {
+        status.is_conflicted()
+            || status.is_gitlink()
+            || nested_prefixes
+                .iter()
+                .any(|prefix| status.path.starts_with(prefix))
+    };
+    let mut removed = vec![false; statuses.len()];
+
+    // HEAD -> index
+    let base_tree = repo
+        .find_tree(base_tree_id)
+        .map_err(Error::object(base_tree_id))?;
+    let index = repo.index_or_empty().map_err(Error::IndexCorrupt)?;
+    let mut sources = Vec::new();
+    let mut dests = Vec::new();
+    for (pos, status) in statuses.iter().enumerate() {
+        if skip(status) {
+            continue;
+        }
+        let copy_only = match status.index_status {
+            StatusChar::Deleted => false,
+            StatusChar::Modified if copies => true,
+            StatusChar::Added => {
+                if let Some(entry) = index.entry_by_path(status.path.as_ref()) {
+                    dests.push(Side {
+                        pos,
+                        id: entry.id,
+                        copy_only: false,
+                    });
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        let path_iter = status.path.split(|&b| b == b'/');
+        if let Some(entry) = base_tree.lookup_entry(path_iter)? {
+            sources.push(Side {
+                pos,
+                id: entry.object_id(),
+                copy_only,
+            });
+        }
+    }
+    let pairs = pair(repo, &sources, &dests, copies, options, |dest| {
+        blob_content(repo, dest.id, options)
+    })?;
+    for (dest, src, kind) in pairs {
+        let orig_path = statuses[src].path.clone();
+        let status = &mut statuses[dest];
+        status.index_status = kind;
+        status.orig_path = Some(orig_path);
+        if kind == StatusChar::Renamed {
+            removed[src] = true;
+        }
+    }
+
+    // index -> worktree
+    let mut sources = Vec::new();
+    for (pos, status) in statuses.iter().enumerate() {
+        if removed[pos] || status.index_status != StatusChar::None || skip(status) {
+            continue;
+        }
+        let copy_only = match status.worktree_status {
+            StatusChar::Deleted => false,
+            StatusChar::Modified if copies => true,
+            _ => continue,
+        };
+        match index.entry_by_path(status.path.as_ref()) {
+            Some(entry) if !entry.flags.contains(Flags::INTENT_TO_ADD) => {
+                sources.push(Side {
+                    pos,
+                    id: entry.id,
+                    copy_only,
+                });
+            }
+            _ => {}
+        }
+    }
+    if !sources.is_empty() {
+        let mut hasher = WorktreeHasher::new(repo)?;
+        let mut dests = Vec::new();
+        for (pos, status) in statuses.iter().enumerate() {
+            // untracked files, and `git add -N` ones
+            let added = status.worktree_status == StatusChar::Untracked
+                || (status.worktree_status == StatusChar::Added
+                    && status.index_status == StatusChar::None);
+            if !added || skip(status) {
+                continue;
+            }
+            let full_path = crate::repo::worktree_path(work_dir, status.path.as_bstr());
+            if too_big(&full_path, options.max_size) {
+                continue;
+            }
+            if let Ok(id) = hasher.hash_file(repo, &index, status.path.as_ref(), &full_path) {
+                dests.push(Side {
+                    pos,
+                    id,
+                    copy_only: false,
+                });
+            }
+        }
+        let pairs = pair(repo, &sources, &dests, copies, options, |dest| {
+            let path = statuses[dest.pos].path.as_bstr();
+            let full_path = crate::repo::worktree_path(work_dir, path);
+            if too_big(&full_path, Some(options.max_load_size)) {
+                return Ok(None);
+            }
+            Ok(hasher.read_file(repo, &index, path, &full_path).ok())
+        })?;
+        for (dest, src, kind) in pairs {
+            let orig_path = statuses[src].path.clone();
+            let status = &mut statuses[dest];
+            status.index_status = StatusChar::None;
+            status.worktree_status = kind;
+            status.orig_path = Some(orig_path);
+            if kind == StatusChar::Renamed {
+                removed[src] = true;
+            }
+        }
+    }
+
+    Ok(statuses
+        .into_iter()
+        .zip(removed)
+        .filter_map(|(status, removed)| (!removed).then_some(status))
+        .collect())
+}
+
+fn too_big(path: &Path, max_size: Option<u64>) -> bool {
+    std::fs::symlink_metadata(path).is_ok_and(|meta| max_size.is_some_and(|max| meta.len() > max))
+}
+
+/// Content of blob `id` for inexact matching, `None` over the size limit
+fn blob_content(
+    repo: &Repository,
+    id: ObjectId,
+    options: &RenameOptions,
+) -> Result<Option<Vec<u8>>> {
+    if repo.find_header(id).map_err(Error::object(id))?.size() > options.max_load_size {
+        return Ok(None);
+    }
+    let data = repo
+        .find_object(id)
+        .map_err(Error::object(id))?
+        .detach()
+        .data;
+    Ok(Some(data))
+}
+
+/// Returns `(dest pos, source pos, Renamed | Copied)`, exact matches first, then
+/// the best-scoring inexact matches above the threshold. Exact matches are found
+/// by id alone; only the sides left after them are loaded, sources from the
+/// object database and destinations with `dest_content`.
+fn pair(
+    repo: &Repository,
+    sources: &[Side],
+    dests: &[Side],
+    copies: bool,
+    options: &RenameOptions,
+    mut dest_content: impl FnMut(&Side) -> Result<Option<Vec<u8>>>,
+) -> Result<Vec<(usize, usize, StatusChar)>> {
+    let mut pairs = Vec::new();
+    let mut source_used = vec![false; sources.len()];
+    let mut dest_done = vec![false; dests.len()];
+
+    let mut assign = |src: usize, dest: usize, source_used: &mut Vec<bool>| -> bool {
+        let kind = if !sources[src].copy_only && !source_used[src] {
+            StatusChar::Renamed
+        } else if copies {
+            StatusChar::Copied
+        } else {
+            return false;
+        };
+        source_used[src] = true;
+        pairs.push((dests[dest].pos, sources[src].pos, kind));
+        true
+    };
+
+    // git never pairs empty files
+    let usable = |side: &Side| !side.id.is_empty_blob();
+
+    for (d, dest) in dests.iter().enumerate().filter(|(_, d)| usable(d)) {
+        let exact = sources
+            .iter()
+            .enumerate()
+            .filter(|(_, src)| usable(src) && src.id == dest.id)
+            .map(|(s, _)| s)
+            .min_by_key(|&s| (source_used[s] || sources[s].copy_only, s));
+        if let Some(s) = exact {
+            dest_done[d] = assign(s, d, &mut source_used);
+        }
+    }
+
+    let remaining_dests: Vec<usize> = (0..dests.len())
+        .filter(|&d| !dest_done[d] && usable(&dests[d]))
+        .collect();
+    let remaining_sources: Vec<usize> = (0..sources.len())
+        .filter(|&s| usable(&sources[s]) && (copies || !source_used[s]))
+        .collect();
+    if remaining_dests.is_empty()
+        || remaining_sources.is_empty()
+        || remaining_dests.len() * remaining_sources.len() > options.limit * options.limit
+    {
+        return Ok(pairs);
+    }
+
+    // content over the size limit takes no part
+    let mut source_chunks = Vec::new();
+    for s in remaining_sources {
+        if let Some(data) = blob_content(repo, sources[s].id, options)? {
+            source_chunks.push((s, data.len(), chunk_counts(&data)));
+        }
+    }
+    let mut scored = Vec::new();
+    for d in remaining_dests {
+        let Some(dest_data) = dest_content(&dests[d])? else {
+            continue;
+        };
+        let dest_len = dest_data.len();
+        let dest_chunks = chunk_counts(&dest_data);
+        for (s, src_len, src_chunks) in &source_chunks {
+            let (s, src_len) = (*s, *src_len);
+            // the score is relative to the larger side, so a lopsided pair can't reach it
+            if src_len.min(dest_len) * 100 < src_len.max(dest_len) * options.threshold as usize {
+                continue;
+            }
+            let score = similarity(src_chunks, &dest_chunks, src_len.max(dest_len));
+            if score >= options.threshold {
+                scored.push((score, d, s));
+            }
+        }
+    }
+    scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+    for (_, d, s) in scored {
+        if !dest_done[d] {
+            dest_done[d] = assign(s, d, &mut source_used);
+        }
+    }
+
+    Ok(pairs)
+}
+
+/// Bytes per content chunk, where chunks end at a newline or after 64 bytes,
+/// like git's `diffcore-delta`
+fn chunk_counts(data: &[u8]) -> HashMap<u64, usize> {
+    let mut counts = HashMap::new();
+    for chunk in data.split_inclusive(|&b| b == b'\n') {
+        for piece in chunk.chunks(64) {
+            let mut hasher = DefaultHasher::new();
+            piece.hash(&mut hasher);
+            *counts.entry(hasher.finish()).or_insert(0) += piece.len();
+        }
+    }
+    counts
+}
+
+/// Shared bytes as a percentage of the larger blob
+fn similarity(src: &HashMap<u64, usize>, dest: &HashMap<u64, usize>, max_len: usize) -> u8 {
+    let shared: usize = src
+        .iter()
+        .filter_map(|(hash, &count)| dest.get(hash).map(|&other| count.min(other)))
+        .sum();
+    (shared * 100 / max_len.max(1)) as u8
+}
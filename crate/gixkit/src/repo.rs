@@ -1,27 +1,202 @@
-use anyhow::Result;
+use crate::{Error, Result};
+use gix::bstr::ByteSlice;
 use gix::Repository;
 
 /// Open a repository with proper error handling
+///
+/// [`Error::NotARepository`] when there is no repository at `path`, as from
+/// [`discover_repo`]; [`Error::Open`] when there is one that can't be opened,
+/// with the cause in the error chain, e.g. an object format this gix version
+/// can't read.
 pub fn open_repo(path: impl AsRef<std::path::Path>) -> Result<Repository> {
-    gix::open(path.as_ref()).map_err(|e| {
-        anyhow::anyhow!(
-            "Failed to open git repository '{}': {}",
-            path.as_ref().display(),
-            e
-        )
+    let path = path.as_ref().to_owned();
+    gix::open(&path).map_err(|e| {
+        if e.is_not_found() {
+            Error::NotARepository {
+                path,
+                source: Some(e.into()),
+            }
+        } else {
+            Error::Open { path, source: e }
+        }
     })
 }
 
+/// Find the repository the way git does from `dir`: `GIT_DIR` (with
+/// `GIT_WORK_TREE`) when set, otherwise the nearest `.git` directory or gitfile
+/// in `dir` or its parents, stopping at `GIT_CEILING_DIRECTORIES`. Linked
+/// worktrees and submodules are found through their `.git` file.
+pub fn discover_repo(dir: impl AsRef<std::path::Path>) -> Result<Repository> {
+    let options = gix::discover::upwards::Options {
+        // like git, ceiling directories that don't contain `dir` are just ignored
+        match_ceiling_dir_or_error: false,
+        ..Default::default()
+    };
+    let not_a_repo = |source: Option<crate::error::BoxError>| Error::NotARepository {
+        path: dir.as_ref().to_owned(),
+        source,
+    };
+    let repo: Repository = gix::ThreadSafeRepository::discover_with_environment_overrides_opts(
+        dir.as_ref(),
+        options,
+        Default::default(),
+    )
+    .map_err(|e| not_a_repo(Some(e.into())))?
+    .into();
+
+    // gix also looks in the ceiling directory itself, which git never moves up into
+    if std::env::var_os("GIT_DIR").is_none() {
+        let found = repo.workdir().unwrap_or_else(|| repo.git_dir());
+        if above_ceiling(dir.as_ref(), found) {
+            return Err(not_a_repo(None));
+        }
+    }
+    Ok(repo)
+}
+
+/// Whether reaching `found` from `dir` meant moving up into or past one of the
+/// `GIT_CEILING_DIRECTORIES`
+fn above_ceiling(dir: &std::path::Path, found: &std::path::Path) -> bool {
+    let Some(ceilings) = std::env::var_os("GIT_CEILING_DIRECTORIES") else {
+        return false;
+    };
+    let (Ok(dir), Ok(found)) = (dir.canonicalize(), found.canonicalize()) else {
+        return false;
+    };
+    std::env::split_paths(&ceilings)
+        .filter(|ceiling| ceiling.is_absolute())
+        .filter_map(|ceiling| ceiling.canonicalize().ok())
+        .any(|ceiling| dir != ceiling && dir.starts_with(&ceiling) && ceiling.starts_with(&found))
+}
+
+/// Every checkout of the repository `repo` belongs to: the main worktree first,
+/// unless the repository is bare, then linked worktrees sorted by path, as
+/// `git worktree list` prints them
+pub fn list_worktrees(repo: &Repository) -> Result<Vec<crate::WorktreeInfo>> {
+    let head = |repo: &Repository| {
+        let id = repo.head_id().ok().map(|id| id.detach());
+        let branch = repo
+            .head_name()
+            .ok()
+            .flatten()
+            .map(|name| name.shorten().to_owned());
+        (id, branch)
+    };
+
+    let main = repo.main_repo().map_err(|e| Error::Open {
+        path: repo.common_dir().to_owned(),
+        source: e,
+    })?;
+    let mut worktrees = Vec::new();
+    if let Some(path) = main.workdir() {
+        let (head, branch) = head(&main);
+        worktrees.push(crate::WorktreeInfo {
+            path: path.to_path_buf(),
+            is_main: true,
+            head,
+            branch,
+            locked: false,
+            prunable: false,
+        });
+    }
+
+    let mut linked = Vec::new();
+    for proxy in main.worktrees().map_err(Error::git)? {
+        let path = proxy.base().map_err(Error::git)?;
+        let locked = proxy.is_locked();
+        let prunable = !path.is_dir();
+        let (head, branch) = match proxy.into_repo_with_possibly_inaccessible_worktree() {
+            Ok(linked_repo) => head(&linked_repo),
+            Err(_) => (None, None),
+        };
+        linked.push(crate::WorktreeInfo {
+            path,
+            is_main: false,
+            head,
+            branch,
+            locked,
+            prunable,
+        });
+    }
+    linked.sort_by(|a, b| a.path.cmp(&b.path));
+    worktrees.extend(linked);
+    Ok(worktrees)
+}
+
 /// Get HEAD commit, falling back to empty tree for empty repos
 pub fn get_head_tree(repo: &Repository) -> Result<gix::Tree<'_>> {
     match repo.head_commit() {
-        Ok(commit) => commit
-            .tree()
-            .map_err(|e| anyhow::anyhow!("Failed to get HEAD tree: {}", e)),
+        Ok(commit) => commit.tree().map_err(Error::git),
         Err(_) => {
-            let oid = gix_hash::ObjectId::empty_tree(gix::hash::Kind::Sha1);
-            repo.find_tree(oid)
-                .map_err(|e| anyhow::anyhow!("Failed to find empty tree: {}", e))
+            let oid = gix_hash::ObjectId::empty_tree(repo.object_hash());
+            repo.find_tree(oid).map_err(Error::object(oid))
+        }
+    }
+}
+
+/// Tree of a revision: anything `git rev-parse` takes (a branch, tag, `@{upstream}`,
+/// `HEAD~3`), or `a...b` for the merge base of `a` and `b`, as `git diff a...b`
+/// compares against
+pub fn get_rev_tree<'repo>(repo: &'repo Repository, rev: &str) -> Result<gix::Tree<'repo>> {
+    let spec = repo
+        .rev_parse(rev)
+        .map_err(|e| Error::RevisionNotFound {
+            rev: rev.to_owned(),
+            source: e,
+        })?
+        .detach();
+    let id = match spec {
+        gix::revision::plumbing::Spec::Include(id) => id,
+        gix::revision::plumbing::Spec::Merge { theirs, ours } => merge_base(repo, theirs, ours)?,
+        _ => {
+            return Err(Error::RevisionRange {
+                rev: rev.to_owned(),
+            })
         }
+    };
+    repo.find_object(id)
+        .map_err(Error::object(id))?
+        .peel_to_tree()
+        .map_err(|e| Error::NotATree {
+            rev: rev.to_owned(),
+            source: e.into(),
+        })
+}
+
+/// Newest commit reachable from both `a` and `b`
+fn merge_base(
+    repo: &Repository,
+    a: gix_hash::ObjectId,
+    b: gix_hash::ObjectId,
+) -> Result<gix_hash::ObjectId> {
+    let reachable_from_a = repo
+        .rev_walk([a])
+        .all()?
+        .map(|info| info.map(|info| info.id))
+        .collect::<Result<std::collections::HashSet<_>, _>>()?;
+    for info in repo
+        .rev_walk([b])
+        .sorting(gix::revision::walk::Sorting::ByCommitTime(
+            gix::traverse::commit::simple::CommitTimeOrder::NewestFirst,
+        ))
+        .all()?
+    {
+        let id = info?.id;
+        if reachable_from_a.contains(&id) {
+            return Ok(id);
+        }
+    }
+    Err(Error::NoMergeBase { a, b })
+}
+
+/// `rel_path`, from the index or a tree, below `work_dir`. Off Unix a path that
+/// isn't UTF-8 can't exist on disk; it comes out lossy, and isn't found.
+pub(crate) fn worktree_path(
+    work_dir: &std::path::Path,
+    rel_path: &gix::bstr::BStr,
+) -> std::path::PathBuf {
+    match gix::path::from_bstr(rel_path) {
+        Ok(path) => work_dir.join(path),
+        Err(_) => work_dir.join(rel_path.to_str_lossy().as_ref()),
     }
 }
@@ -1,14 +1,23 @@
-use anyhow::Result;
+use crate::{Error, Result};
+use gix::worktree::stack::state::attributes::Source as AttributesSource;
 use gix::{
-    bstr::{BStr, BString},
+    bstr::{BStr, BString, ByteSlice, ByteVec},
+    index::entry::{stat, Flags, Mode, Stage, Stat},
     Repository,
 };
 use gix_hash::ObjectId;
-use gix_object::Kind;
+use std::collections::{HashSet, VecDeque};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use crate::types::{FileMetadata, FileStatus, StatusChar};
+use crate::cache::StatusCache;
+use crate::parallel::Parallel;
+use crate::rename::RenameOptions;
+use crate::types::{
+    ConflictStages, FileMetadata, FileModes, FileStatus, IgnoredFilter, RenameDetection,
+    StatusChar, SubmoduleState, UntrackedFilter,
+};
+use crate::worktree::WorktreeHasher;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IterMode {
@@ -19,67 +28,274 @@ pub enum IterMode {
 
 pub struct RepoIterBuilder {
     repo: Arc<Repository>,
-    mode: IterMode,
-    status_filter: Option<Vec<StatusChar>>,
-    include_metadata: bool,
-    subdir: Option<PathBuf>,
+    options: IterOptions,
+}
+
+/// Everything a `RepoIter` is configured with besides the repository, so
+/// parallel workers can set up their own
+#[derive(Clone)]
+pub(crate) struct IterOptions {
+    pub mode: IterMode,
+    pub status_filter: Option<Vec<StatusChar>>,
+    pub include_metadata: bool,
+    pub subdir: Option<PathBuf>,
+    pub pathspec: Vec<BString>,
+    pub base: Option<String>,
+    pub untracked: Option<UntrackedFilter>,
+    pub ignored: IgnoredFilter,
+    pub compare_content: bool,
+    pub max_hash_size: Option<u64>,
+    pub renames: Option<RenameDetection>,
+    pub rename_threshold: Option<u8>,
+    pub recurse_submodules: bool,
+    pub recurse_nested_repos: bool,
+    pub threads: Option<usize>,
+    pub ordered: bool,
+    pub status_cache: bool,
+    pub write_untracked_cache: bool,
+    /// Loaded by `build`, shared with parallel workers
+    pub cache: Option<Arc<StatusCache>>,
 }
 
 impl RepoIterBuilder {
     pub fn new(repo: Arc<Repository>) -> Self {
         Self {
             repo,
-            mode: IterMode::Both,
-            status_filter: None,
-            include_metadata: false,
-            subdir: None,
+            options: IterOptions {
+                mode: IterMode::Both,
+                status_filter: None,
+                include_metadata: false,
+                subdir: None,
+                pathspec: Vec::new(),
+                base: None,
+                untracked: None,
+                ignored: IgnoredFilter::No,
+                compare_content: false,
+                max_hash_size: None,
+                renames: None,
+                rename_threshold: None,
+                recurse_submodules: false,
+                recurse_nested_repos: false,
+                threads: None,
+                ordered: true,
+                status_cache: false,
+                write_untracked_cache: true,
+                cache: None,
+            },
         }
     }
 
     pub fn mode(mut self, mode: IterMode) -> Self {
-        self.mode = mode;
+        self.options.mode = mode;
         self
     }
 
     pub fn filter(mut self, chars: Vec<StatusChar>) -> Self {
-        self.status_filter = Some(chars);
+        self.options.status_filter = Some(chars);
         self
     }
 
     pub fn include_metadata(mut self, include: bool) -> Self {
-        self.include_metadata = include;
+        self.options.include_metadata = include;
         self
     }
 
     pub fn subdir(mut self, path: impl AsRef<Path>) -> Self {
-        self.subdir = Some(path.as_ref().to_path_buf());
+        self.options.subdir = Some(path.as_ref().to_path_buf());
         self
     }
 
-    pub fn build(self) -> Result<RepoIter> {
-        RepoIter::new(
-            self.repo,
-            self.mode,
-            self.status_filter,
-            self.include_metadata,
-            self.subdir,
-        )
+    /// Limit iteration to git pathspecs: literal paths, globs and `:(exclude)`,
+    /// `:(icase)`, `:(top)` magic, relative to the current directory like
+    /// `git status -- <pathspec>`
+    pub fn pathspec<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<[u8]>,
+    {
+        self.options
+            .pathspec
+            .extend(patterns.into_iter().map(|p| BString::from(p.as_ref())));
+        self
+    }
+
+    /// Compare the index against this revision's tree instead of HEAD's: a
+    /// branch, tag, `@{upstream}`, `HEAD~3`, or `main...HEAD` for the merge base
+    pub fn base(mut self, rev: impl Into<String>) -> Self {
+        self.options.base = Some(rev.into());
+        self
+    }
+
+    /// Override `status.showUntrackedFiles`: `Normal` reports a directory
+    /// holding no tracked files as one `dir/` entry, `All` lists every file in
+    /// it, `No` skips untracked files altogether
+    pub fn untracked(mut self, filter: UntrackedFilter) -> Self {
+        self.options.untracked = Some(filter);
+        self
+    }
+
+    /// Also yield ignored paths found by the untracked walk, as `StatusChar::Ignored`,
+    /// like `git status --ignored=<mode>`
+    pub fn ignored(mut self, filter: IgnoredFilter) -> Self {
+        self.options.ignored = filter;
+        self
+    }
+
+    /// Hash every tracked file instead of trusting matching index stat data
+    pub fn compare_content(mut self, force: bool) -> Self {
+        self.options.compare_content = force;
+        self
+    }
+
+    /// Never read files larger than `bytes`: their stat data alone decides
+    /// whether they changed, and rename detection pairs them only as exact
+    /// renames by the ids the index and the base tree already have
+    pub fn max_hash_size(mut self, bytes: u64) -> Self {
+        self.options.max_hash_size = Some(bytes);
+        self
+    }
+
+    /// Override `status.renames` / `diff.renames`
+    pub fn renames(mut self, detection: RenameDetection) -> Self {
+        self.options.renames = Some(detection);
+        self
+    }
+
+    /// Minimum similarity, in percent, for an inexact rename or copy
+    pub fn rename_threshold(mut self, percent: u8) -> Self {
+        self.options.rename_threshold = Some(percent);
+        self
+    }
+
+    /// Also yield the changes inside checked-out submodules, as `sub/path`
+    pub fn recurse_submodules(mut self, recurse: bool) -> Self {
+        self.options.recurse_submodules = recurse;
+        self
+    }
+
+    /// Also yield the status of repositories found inside the worktree, which
+    /// are otherwise reported as a single untracked `dir/`, as `dir/path`
+    pub fn recurse_nested_repos(mut self, recurse: bool) -> Self {
+        self.options.recurse_nested_repos = recurse;
+        self
+    }
+
+    /// Keep the blob ids of hashed worktree files in `.git/got/status-cache`, so
+    /// a repeat run doesn't read files that haven't been touched since
+    pub fn status_cache(mut self, enable: bool) -> Self {
+        self.options.status_cache = enable;
+        self
+    }
+
+    /// Write the untracked cache back into the index after the walk (the
+    /// default), as `git status` does. Without it the cache is still read, and
+    /// the index file is left alone, for callers watching it for changes.
+    pub fn write_untracked_cache(mut self, write: bool) -> Self {
+        self.options.write_untracked_cache = write;
+        self
+    }
+
+    /// Compare tracked entries on a pool of `threads` workers, with the
+    /// untracked walk as one more task beside them; 0 starts one per available
+    /// core
+    pub fn parallel(mut self, threads: usize) -> Self {
+        self.options.threads = Some(threads);
+        self
+    }
+
+    /// In parallel mode, yield results in the sequential iterator's order
+    /// (the default) rather than as workers finish them: tracked paths in
+    /// index order, then untracked paths sorted, ignored ones last
+    pub fn ordered(mut self, ordered: bool) -> Self {
+        self.options.ordered = ordered;
+        self
+    }
+
+    pub fn build(mut self) -> Result<RepoIter> {
+        if self.options.status_cache && !self.options.compare_content {
+            let index = self.repo.index_or_empty().map_err(Error::IndexCorrupt)?;
+            self.options.cache = Some(Arc::new(StatusCache::load(&self.repo, &index)));
+        }
+        RepoIter::new(self.repo, self.options)
     }
 }
 
 pub struct RepoIter {
     repo: Arc<Repository>,
     work_dir: PathBuf,
-    head_tree_id: ObjectId,
-    tracked_iter: std::vec::IntoIter<(BString, ObjectId)>,
-    untracked_dir_stack: Vec<PathBuf>,
-    untracked_current_iter: Option<std::fs::ReadDir>,
+    base_tree_id: ObjectId,
+    tracked_iter: std::vec::IntoIter<TrackedItem>,
+    /// The untracked walk is still to be done
+    untracked_walk: bool,
+    /// What `got-untracked` is asked for: the untracked and ignored filters,
+    /// subdir and pathspec
+    walk_options: got_untracked::StatusOptions,
+    pathspec: Option<gix::PathspecDetached>,
     mode: IterMode,
     status_filter: Option<Vec<StatusChar>>,
     include_metadata: bool,
+    stat_options: stat::Options,
+    compare_content: bool,
+    max_hash_size: Option<u64>,
+    renames: RenameOptions,
+    collected: Option<std::vec::IntoIter<Result<FileStatus>>>,
+    parallel: Option<Parallel>,
+    recurse_submodules: bool,
+    recurse_nested_repos: bool,
+    /// Statuses found ahead of time and waiting to be yielded: those inside a
+    /// submodule, and the untracked walk's
+    pending: VecDeque<Result<FileStatus>>,
+    index: gix::worktree::Index,
+    hasher: Option<WorktreeHasher>,
+    cache: Option<Arc<StatusCache>>,
+    executable_bit: bool,
+    symlinks: bool,
     phase: IterationPhase,
 }
 
+pub(crate) struct TrackedEntry {
+    path: BString,
+    id: ObjectId,
+    mode: Mode,
+    stat: Stat,
+    racy: bool,
+    flags: Flags,
+}
+
+impl TrackedEntry {
+    /// Skip-worktree (outside the sparse checkout) or assume-unchanged: the user
+    /// promised the worktree copy doesn't matter, so it is never looked at
+    fn skips_worktree(&self) -> bool {
+        self.flags
+            .intersects(Flags::SKIP_WORKTREE | Flags::ASSUME_VALID)
+    }
+
+    /// `git add -N`: a placeholder that isn't in the index as far as the base
+    /// tree is concerned, and is a new file in the worktree
+    fn intent_to_add(&self) -> bool {
+        self.flags.contains(Flags::INTENT_TO_ADD)
+    }
+}
+
+pub(crate) enum TrackedItem {
+    Entry(TrackedEntry),
+    /// In the base tree (HEAD unless `base` is set) but not in the index, i.e.
+    /// staged for deletion
+    HeadOnly(BString, Mode),
+    /// Unmerged path, with the stage 1/2/3 entries grouped together
+    Conflict(BString, ConflictStages),
+}
+
+impl TrackedItem {
+    fn path(&self) -> &BStr {
+        match self {
+            TrackedItem::Entry(entry) => entry.path.as_ref(),
+            TrackedItem::HeadOnly(path, _) => path.as_ref(),
+            TrackedItem::Conflict(path, _) => path.as_ref(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum IterationPhase {
     Tracked,
@@ -87,87 +303,272 @@ enum IterationPhase {
 }
 
 impl RepoIter {
-    fn new(
-        repo: Arc<Repository>,
-        mode: IterMode,
-        status_filter: Option<Vec<StatusChar>>,
-        include_metadata: bool,
-        subdir: Option<PathBuf>,
-    ) -> Result<Self> {
-        let work_dir = repo
-            .work_dir()
-            .ok_or_else(|| anyhow::anyhow!("Repository has no working directory"))?
-            .to_path_buf();
-
-        let head_tree_id: ObjectId = {
-            let head_tree = crate::get_head_tree(&repo)?;
-            head_tree.id().into()
-        };
+    fn new(repo: Arc<Repository>, options: IterOptions) -> Result<Self> {
+        let mut iter = Self::idle(repo, &options)?;
 
-        let tracked_iter = if mode != IterMode::Untracked {
-            let index = repo.index()?;
-            let entries: Vec<(BString, ObjectId)> = if let Some(ref subdir) = subdir {
-                let subdir_str = subdir.to_string_lossy().to_string();
-                index
-                    .entries()
-                    .iter()
-                    .filter(|entry| entry.path(&index).to_string().starts_with(&subdir_str))
-                    .map(|entry| (entry.path(&index).to_owned(), entry.id))
-                    .collect()
-            } else {
-                index
-                    .entries()
-                    .iter()
-                    .map(|entry| (entry.path(&index).to_owned(), entry.id))
-                    .collect()
-            };
-            entries.into_iter()
+        let tracked = if options.mode != IterMode::Untracked {
+            iter.tracked_items(options.subdir.as_deref())?
         } else {
-            Vec::new().into_iter()
+            Vec::new()
+        };
+        let untracked =
+            options.mode != IterMode::Tracked && iter.walk_options.untracked != UntrackedFilter::No;
+
+        match options.threads {
+            Some(threads) => {
+                iter.parallel = Some(Parallel::spawn(
+                    &iter.repo, options, threads, tracked, untracked,
+                ));
+            }
+            None => {
+                iter.tracked_iter = tracked.into_iter();
+                iter.untracked_walk = untracked;
+            }
+        }
+        Ok(iter)
+    }
+
+    /// A parallel worker: set up like the iterator it serves, but fed tasks
+    /// instead of walking the repository itself
+    pub(crate) fn worker(repo: Arc<Repository>, options: &IterOptions) -> Result<Self> {
+        Self::idle(repo, options)
+    }
+
+    /// Everything but the work lists
+    fn idle(repo: Arc<Repository>, options: &IterOptions) -> Result<Self> {
+        let mode = options.mode;
+        let work_dir = repo.workdir().ok_or(Error::BareRepository)?.to_path_buf();
+
+        // what the index column is against: HEAD, or the requested revision
+        let base_tree_id: ObjectId = match options.base {
+            Some(ref rev) => crate::get_rev_tree(&repo, rev)?.id,
+            None => crate::get_head_tree(&repo)?.id,
         };
 
-        let untracked_start_dir = if let Some(ref subdir) = subdir {
-            work_dir.join(subdir)
+        let pathspec = if options.pathspec.is_empty() {
+            None
         } else {
-            work_dir.clone()
+            let index = repo.index_or_empty().map_err(Error::IndexCorrupt)?;
+            let spec = repo.pathspec(
+                false,
+                &options.pathspec,
+                true,
+                &index,
+                AttributesSource::WorktreeThenIdMapping,
+            )?;
+            Some(spec.detach().map_err(Error::git)?)
+        };
+
+        let untracked = options
+            .untracked
+            .unwrap_or_else(|| got_untracked::configured_untracked(&repo));
+        if untracked == UntrackedFilter::No && options.ignored == IgnoredFilter::Matching {
+            return Err(Error::UnsupportedOptions);
+        }
+
+        let stat_options = got_untracked::stat_options(&repo);
+        let renames = RenameOptions::resolve(
+            &repo,
+            options.renames,
+            options.rename_threshold,
+            options.max_hash_size,
+        )?;
+
+        let (executable_bit, symlinks) = {
+            let config = repo.config_snapshot();
+            (
+                config.boolean("core.fileMode").unwrap_or(true),
+                config.boolean("core.symlinks").unwrap_or(true),
+            )
         };
 
-        let untracked_dir_stack = if mode != IterMode::Tracked {
-            vec![untracked_start_dir]
+        let index = repo.index_or_empty().map_err(Error::IndexCorrupt)?;
+        let hasher = if mode != IterMode::Untracked {
+            Some(WorktreeHasher::new(&repo)?)
         } else {
-            vec![]
+            None
         };
 
         Ok(Self {
             repo,
             work_dir,
-            head_tree_id,
-            tracked_iter,
-            untracked_dir_stack,
-            untracked_current_iter: None,
+            base_tree_id,
+            tracked_iter: Vec::new().into_iter(),
+            untracked_walk: false,
+            walk_options: got_untracked::StatusOptions {
+                untracked,
+                ignored: options.ignored,
+                subdir: options.subdir.clone(),
+                pathspec: options.pathspec.clone(),
+                keep_index: !options.write_untracked_cache,
+            },
+            pathspec,
             mode,
-            status_filter,
-            include_metadata,
+            status_filter: options.status_filter.clone(),
+            include_metadata: options.include_metadata,
+            stat_options,
+            compare_content: options.compare_content,
+            max_hash_size: options.max_hash_size,
+            renames,
+            collected: None,
+            parallel: None,
+            recurse_submodules: options.recurse_submodules,
+            recurse_nested_repos: options.recurse_nested_repos,
+            pending: VecDeque::new(),
+            index,
+            hasher,
+            cache: options.cache.clone(),
+            executable_bit,
+            symlinks,
             phase: IterationPhase::Tracked,
         })
     }
 
+    /// Index entries, unmerged paths and HEAD-only paths under `subdir` and the
+    /// pathspec, in path order
+    fn tracked_items(&mut self, subdir: Option<&Path>) -> Result<Vec<TrackedItem>> {
+        let index = &self.index;
+        let timestamp = index.timestamp();
+        let stat_options = self.stat_options;
+        let to_tracked = |entry: &gix::index::Entry| TrackedEntry {
+            path: entry.path(index).to_owned(),
+            id: entry.id,
+            mode: entry.mode,
+            stat: entry.stat,
+            racy: entry.stat.is_racy(timestamp, stat_options),
+            flags: entry.flags,
+        };
+        let mut items: Vec<TrackedItem> = Vec::new();
+        let mut sparse_dirs: Vec<(BString, ObjectId)> = Vec::new();
+        for entry in index.entries() {
+            let path = entry.path(index);
+            // a sparse index folds whole directories outside the cone into one entry
+            if entry.mode.is_sparse() {
+                let dir = path.strip_suffix(b"/").unwrap_or(path);
+                sparse_dirs.push((dir_path(dir.as_bstr()), entry.id));
+                continue;
+            }
+            if !in_subdir(path, subdir)
+                || self
+                    .pathspec
+                    .as_mut()
+                    .is_some_and(|spec| !spec.is_included(path, Some(false)))
+            {
+                continue;
+            }
+
+            let stage = entry.stage();
+            if stage == Stage::Unconflicted {
+                items.push(TrackedItem::Entry(to_tracked(entry)));
+                continue;
+            }
+
+            // stages of one path are adjacent in the index
+            let stages = match items.last_mut() {
+                Some(TrackedItem::Conflict(conflict_path, stages)) if conflict_path == path => {
+                    stages
+                }
+                _ => {
+                    items.push(TrackedItem::Conflict(
+                        path.to_owned(),
+                        ConflictStages::default(),
+                    ));
+                    match items.last_mut() {
+                        Some(TrackedItem::Conflict(_, stages)) => stages,
+                        _ => unreachable!(),
+                    }
+                }
+            };
+            match stage {
+                Stage::Base => stages.base = Some(entry.id),
+                Stage::Ours => stages.ours = Some(entry.id),
+                Stage::Theirs => stages.theirs = Some(entry.id),
+                Stage::Unconflicted => {}
+            }
+        }
+        let base_tree = self
+            .repo
+            .find_tree(self.base_tree_id)
+            .map_err(Error::object(self.base_tree_id))?;
+        for (dir, id) in &sparse_dirs {
+            let Some(entries) = sparse_dir_changes(&self.repo, &base_tree, dir.as_ref(), *id)?
+            else {
+                continue;
+            };
+            for (path, id, mode) in entries {
+                if !in_subdir(path.as_ref(), subdir)
+                    || self
+                        .pathspec
+                        .as_mut()
+                        .is_some_and(|spec| !spec.is_included(path.as_bstr(), Some(false)))
+                {
+                    continue;
+                }
+                items.push(match id {
+                    Some(id) => TrackedItem::Entry(TrackedEntry {
+                        path,
+                        id,
+                        mode,
+                        stat: Stat::default(),
+                        racy: false,
+                        flags: Flags::SKIP_WORKTREE,
+                    }),
+                    None => TrackedItem::HeadOnly(path, mode),
+                });
+            }
+        }
+        let tracked = |path: &BStr| {
+            index.entry_index_by_path(path).is_ok()
+                || sparse_dirs.iter().any(|(dir, _)| path.starts_with(dir))
+        };
+        items.extend(
+            head_only_paths(&base_tree, tracked, subdir, self.pathspec.as_mut())?
+                .into_iter()
+                .map(|(path, mode)| TrackedItem::HeadOnly(path, mode)),
+        );
+        items.sort_by(|a, b| a.path().cmp(b.path()));
+        Ok(items)
+    }
+
+    /// Statuses of a chunk of tracked items, for a parallel worker
+    pub(crate) fn work_tracked(
+        &mut self,
+        items: Vec<TrackedItem>,
+    ) -> impl Iterator<Item = Result<FileStatus>> + '_ {
+        self.tracked_iter = items.into_iter();
+        std::iter::from_fn(move || self.next_tracked())
+    }
+
+    /// The untracked walk, for a parallel worker
+    pub(crate) fn work_untracked(&mut self) -> impl Iterator<Item = Result<FileStatus>> + '_ {
+        self.untracked_walk = true;
+        std::iter::from_fn(move || self.next_untracked())
+    }
+
     pub fn builder(repo: Arc<Repository>) -> RepoIterBuilder {
         RepoIterBuilder::new(repo)
     }
 
     fn next_tracked(&mut self) -> Option<Result<FileStatus>> {
-        while let Some((path, oid)) = self.tracked_iter.next() {
-            let file_status = self.compute_file_status(path, oid);
+        loop {
+            // files inside the submodule yielded last
+            if let Some(result) = self.pending.pop_front() {
+                return Some(result);
+            }
+            let item = self.tracked_iter.next()?;
+            let file_status = match item {
+                TrackedItem::Entry(entry) => self.compute_file_status(entry),
+                TrackedItem::HeadOnly(path, mode) => self.head_only_status(path, mode),
+                TrackedItem::Conflict(path, stages) => self.conflict_status(path, stages),
+            };
 
-            if file_status.has_changes() && !self.should_filter_out_tracked(&file_status) {
+            if file_status.has_changes() {
                 return Some(Ok(file_status));
             }
         }
-        None
     }
 
-    fn should_filter_out_tracked(&self, status: &FileStatus) -> bool {
+    fn should_filter_out(&self, status: &FileStatus) -> bool {
         if let Some(ref filter) = self.status_filter {
             !filter.contains(&status.index_status) && !filter.contains(&status.worktree_status)
         } else {
@@ -175,58 +576,86 @@ impl RepoIter {
         }
     }
 
-    fn compute_file_status(&self, path: BString, entry_oid: ObjectId) -> FileStatus {
+    fn compute_file_status(&mut self, entry: TrackedEntry) -> FileStatus {
+        let path = &entry.path;
+        let entry_oid = entry.id;
         let mut index_status = ' ';
         let mut worktree_status = ' ';
-
-        let head_tree = self.repo.find_tree(self.head_tree_id).ok();
+        let mut head_mode = None;
 
         let path_iter = path.split(|&b| b == b'/');
-        let mut buf = Vec::new();
-
-        if let Some(head_tree) = head_tree {
-            if let Some(head_entry) = head_tree.lookup_entry(path_iter, &mut buf).ok().flatten() {
-                if let Ok(head_obj) = head_entry.object() {
-                    let head_oid = head_obj.id();
-                    if head_oid != entry_oid {
-                        index_status = 'M';
-                    }
+        let intent_to_add = entry.intent_to_add();
+
+        if let Ok(head_tree) = self.repo.find_tree(self.base_tree_id) {
+            if let Some(head_entry) = head_tree.lookup_entry(path_iter).ok().flatten() {
+                let mode = Mode::from(head_entry.mode());
+                head_mode = Some(mode);
+                if intent_to_add {
+                    // `git rm --cached` then `git add -N`: `DA`
+                    index_status = 'D';
+                } else if is_type_change(mode, entry.mode) {
+                    index_status = 'T';
+                } else if mode != entry.mode || head_entry.object_id() != entry_oid {
+                    index_status = 'M';
                 }
-            } else {
+            } else if !intent_to_add {
                 index_status = 'A';
             }
-        } else {
+        } else if !intent_to_add {
             index_status = 'A';
         }
 
-        let path_str = path.to_string();
-        let full_path = self.work_dir.join(&path_str);
+        let full_path = crate::repo::worktree_path(&self.work_dir, path.as_bstr());
 
-        let metadata = if full_path.exists() {
-            if let Ok(content) = std::fs::read(&full_path) {
-                let file_oid =
-                    gix_object::compute_hash(gix::hash::Kind::Sha1, Kind::Blob, &content);
-                if file_oid != entry_oid {
+        let mut worktree_mode = None;
+        let mut submodule = None;
+        let metadata = if let Ok(fs_meta) =
+            gix::index::fs::Metadata::from_path_no_follow(&full_path)
+        {
+            let mode = self.worktree_mode(entry.mode, &fs_meta);
+            if entry.skips_worktree() {
+                // left alone, whatever is on disk
+            } else if entry.mode.is_submodule() {
+                worktree_mode = Some(mode);
+                submodule = self.submodule_state(&entry, &full_path);
+                if submodule.is_some_and(|state| state.is_dirty()) {
+                    worktree_status = 'M';
+                }
+            } else if fs_meta.is_dir() {
+                // a directory took the file's place
+                worktree_status = 'D';
+            } else if intent_to_add {
+                worktree_mode = Some(mode);
+                worktree_status = 'A';
+            } else {
+                worktree_mode = Some(mode);
+                if is_type_change(entry.mode, mode) {
+                    worktree_status = 'T';
+                } else if mode != entry.mode || self.worktree_modified(&entry, &full_path, &fs_meta)
+                {
                     worktree_status = 'M';
                 }
             }
             if self.include_metadata {
-                match std::fs::metadata(&full_path) {
-                    Ok(m) => Some(FileMetadata {
-                        modified_time: m.modified().unwrap_or(std::time::SystemTime::UNIX_EPOCH),
-                        size: m.len(),
-                    }),
-                    Err(_) => None,
-                }
+                Some(FileMetadata {
+                    modified_time: fs_meta
+                        .modified()
+                        .unwrap_or(std::time::SystemTime::UNIX_EPOCH),
+                    size: fs_meta.len(),
+                    newest_modified_time: None,
+                })
             } else {
                 None
             }
         } else {
-            worktree_status = 'D';
+            if !entry.skips_worktree() {
+                worktree_status = 'D';
+            }
             if self.include_metadata {
                 Some(FileMetadata {
                     modified_time: std::time::SystemTime::UNIX_EPOCH,
                     size: 0,
+                    newest_modified_time: None,
                 })
             } else {
                 None
@@ -234,153 +663,705 @@ impl RepoIter {
         };
 
         FileStatus {
-            path: path_str,
+            path: entry.path,
+            orig_path: None,
             index_status: crate::types::StatusChar::from_char(index_status),
             worktree_status: crate::types::StatusChar::from_char(worktree_status),
             metadata,
+            conflict: None,
+            modes: Some(FileModes {
+                head: head_mode,
+                index: Some(entry.mode),
+                worktree: worktree_mode,
+            }),
+            submodule,
         }
     }
 
-    fn next_dir_entry(&mut self) -> Option<Result<std::fs::DirEntry>> {
-        loop {
-            if self.untracked_current_iter.is_none() {
-                if let Some(dir) = self.untracked_dir_stack.pop() {
-                    match std::fs::read_dir(&dir) {
-                        Ok(iter) => self.untracked_current_iter = Some(iter),
-                        Err(e) => return Some(Err(e.into())),
+    /// The mode git would record for the worktree item, keeping the index's
+    /// executable bit or symlink-ness where `core.fileMode` / `core.symlinks` say
+    /// the filesystem can't be trusted with them
+    fn worktree_mode(&self, index_mode: Mode, fs_meta: &gix::index::fs::Metadata) -> Mode {
+        if fs_meta.is_dir() {
+            if index_mode.is_submodule() {
+                Mode::COMMIT
+            } else {
+                Mode::DIR
+            }
+        } else if self.symlinks && fs_meta.is_symlink()
+            || !self.symlinks && index_mode == Mode::SYMLINK
+        {
+            Mode::SYMLINK
+        } else if self.executable_bit && fs_meta.is_executable()
+            || !self.executable_bit && index_mode == Mode::FILE_EXECUTABLE
+        {
+            Mode::FILE_EXECUTABLE
+        } else {
+            Mode::FILE
+        }
+    }
+
+    /// Compare a populated submodule's HEAD with the recorded gitlink and run a
+    /// nested status over it. With recursion on, the nested changes are queued up
+    /// to follow the gitlink, their paths prefixed with the submodule's.
+    fn submodule_state(
+        &mut self,
+        entry: &TrackedEntry,
+        full_path: &Path,
+    ) -> Option<SubmoduleState> {
+        // not checked out
+        let submodule = gix::open(full_path).ok()?;
+        let mut state = SubmoduleState {
+            new_commits: submodule
+                .head_id()
+                .is_ok_and(|head_id| head_id.detach() != entry.id),
+            ..Default::default()
+        };
+
+        let recurse = self.recurse_submodules;
+        let nested = self.nested_builder(submodule, recurse).build().ok()?;
+
+        for result in nested {
+            let mut status = match result {
+                Ok(status) => status,
+                Err(e) => {
+                    if recurse {
+                        self.pending.push_back(Err(e));
                     }
-                } else {
-                    return None;
+                    continue;
                 }
+            };
+            let untracked = status.worktree_status == StatusChar::Untracked;
+            if untracked {
+                state.untracked_content = true;
+            } else {
+                state.modified_content = true;
             }
 
-            if let Some(ref mut iter) = self.untracked_current_iter {
-                match iter.next() {
-                    Some(Ok(entry)) => return Some(Ok(entry)),
-                    Some(Err(_)) => continue,
-                    None => {
-                        self.untracked_current_iter = None;
-                        continue;
-                    }
+            if !recurse {
+                if state.modified_content && state.untracked_content {
+                    break;
                 }
+                continue;
             }
+            if untracked && self.mode == IterMode::Tracked {
+                continue;
+            }
+            prefix_paths(&mut status, entry.path.as_ref());
+            if self
+                .pathspec
+                .as_mut()
+                .is_some_and(|spec| !spec.is_included(status.path.as_bstr(), Some(false)))
+            {
+                continue;
+            }
+            self.pending.push_back(Ok(status));
         }
+        Some(state)
     }
 
-    fn next_untracked(&mut self) -> Option<Result<FileStatus>> {
-        let index = match self.repo.index() {
-            Ok(idx) => idx,
-            Err(_) => return Some(Err(anyhow::anyhow!("Failed to get index"))),
-        };
+    /// A status run over a repository inside this one, a submodule or a nested
+    /// clone, set up like this one. Without `recurse` its results only go into
+    /// a summary, so it skips whatever doesn't change that.
+    fn nested_builder(&self, repo: Repository, recurse: bool) -> RepoIterBuilder {
+        #[allow(clippy::arc_with_non_send_sync)]
+        let repo = Arc::new(repo);
+        let builder = RepoIterBuilder::new(repo)
+            .include_metadata(recurse && self.include_metadata)
+            .compare_content(self.compare_content)
+            .renames(if recurse {
+                self.renames.detection
+            } else {
+                RenameDetection::Off
+            })
+            .rename_threshold(self.renames.threshold)
+            .untracked(if recurse {
+                self.walk_options.untracked
+            } else {
+                UntrackedFilter::Normal
+            })
+            .recurse_submodules(recurse && self.recurse_submodules)
+            .recurse_nested_repos(recurse && self.recurse_nested_repos)
+            .status_cache(self.cache.is_some())
+            .write_untracked_cache(!self.walk_options.keep_index);
+        match self.max_hash_size {
+            Some(bytes) => builder.max_hash_size(bytes),
+            None => builder,
+        }
+    }
 
-        loop {
-            let entry_result = self.next_dir_entry();
-            let entry = match entry_result {
-                Some(Ok(e)) => e,
-                Some(Err(e)) => return Some(Err(e)),
-                None => return None,
-            };
+    /// Queue up the status of a repository nested in the worktree at `rel_path`,
+    /// to follow its `dir/` entry
+    fn queue_nested_repo(&mut self, rel_path: &BStr) {
+        let dir = crate::repo::worktree_path(&self.work_dir, rel_path);
+        let nested = crate::open_repo(&dir)
+            .and_then(|repo| {
+                self.nested_builder(repo, true)
+                    .mode(self.mode)
+                    .ignored(self.walk_options.ignored)
+                    .build()
+            })
+            .map_err(|e| Error::NestedRepository {
+                path: dir,
+                source: Box::new(e),
+            });
+        let nested = match nested {
+            Ok(nested) => nested,
+            Err(e) => {
+                self.pending.push_back(Err(e));
+                return;
+            }
+        };
+        for result in nested {
+            let result = result.map(|mut status| {
+                prefix_paths(&mut status, rel_path);
+                status
+            });
+            if let Ok(ref status) = result {
+                if self
+                    .pathspec
+                    .as_mut()
+                    .is_some_and(|spec| !spec.is_included(status.path.as_bstr(), Some(false)))
+                {
+                    continue;
+                }
+            }
+            self.pending.push_back(result);
+        }
+    }
 
-            let path = entry.path();
+    fn head_only_status(&self, path: BString, mode: Mode) -> FileStatus {
+        let metadata = if self.include_metadata {
+            Some(FileMetadata {
+                modified_time: std::time::SystemTime::UNIX_EPOCH,
+                size: 0,
+                newest_modified_time: None,
+            })
+        } else {
+            None
+        };
 
-            let file_name = match path.file_name() {
-                Some(name) => name,
-                None => continue,
-            };
+        FileStatus {
+            path,
+            orig_path: None,
+            index_status: StatusChar::Deleted,
+            worktree_status: StatusChar::None,
+            metadata,
+            conflict: None,
+            modes: Some(FileModes {
+                head: Some(mode),
+                index: None,
+                worktree: None,
+            }),
+            submodule: None,
+        }
+    }
 
-            let file_name_str = match file_name.to_str() {
-                Some(s) => s,
-                None => continue,
-            };
+    /// `git status` two-letter codes for the stages present: `DD`, `AU`, `UD`,
+    /// `UA`, `DU`, `AA` or `UU`
+    fn conflict_status(&self, path: BString, stages: ConflictStages) -> FileStatus {
+        let (index_status, worktree_status) = match (
+            stages.base.is_some(),
+            stages.ours.is_some(),
+            stages.theirs.is_some(),
+        ) {
+            (true, false, false) => (StatusChar::Deleted, StatusChar::Deleted),
+            (false, true, false) => (StatusChar::Added, StatusChar::Unmerged),
+            (true, true, false) => (StatusChar::Unmerged, StatusChar::Deleted),
+            (false, false, true) => (StatusChar::Unmerged, StatusChar::Added),
+            (true, false, true) => (StatusChar::Deleted, StatusChar::Unmerged),
+            (false, true, true) => (StatusChar::Added, StatusChar::Added),
+            _ => (StatusChar::Unmerged, StatusChar::Unmerged),
+        };
 
-            if file_name_str.starts_with('.') || file_name_str == ".git" {
-                continue;
-            }
+        let metadata = if self.include_metadata {
+            let full_path = crate::repo::worktree_path(&self.work_dir, path.as_bstr());
+            let modified = std::fs::symlink_metadata(full_path).ok();
+            Some(FileMetadata {
+                modified_time: modified
+                    .as_ref()
+                    .and_then(|m| m.modified().ok())
+                    .unwrap_or(std::time::SystemTime::UNIX_EPOCH),
+                size: modified.map_or(0, |m| m.len()),
+                newest_modified_time: None,
+            })
+        } else {
+            None
+        };
 
-            let rel_path = match path.strip_prefix(&self.work_dir) {
-                Ok(p) => p,
-                Err(_) => continue,
-            };
+        FileStatus {
+            path,
+            orig_path: None,
+            index_status,
+            worktree_status,
+            metadata,
+            conflict: Some(stages),
+            modes: None,
+            submodule: None,
+        }
+    }
 
-            let rel_path_str = match rel_path.to_str() {
-                Some(s) => s,
-                None => continue,
-            };
+    /// Compare an index entry against the worktree, the way git's `ie_match_stat` does:
+    /// matching stat data is trusted unless the entry is racy, a size change is a
+    /// modification outright, and only the ambiguous remainder gets hashed.
+    fn worktree_modified(
+        &mut self,
+        entry: &TrackedEntry,
+        full_path: &Path,
+        fs_meta: &gix::index::fs::Metadata,
+    ) -> bool {
+        if !self.compare_content {
+            if let Ok(fs_stat) = Stat::from_fs(fs_meta) {
+                // racily-clean entries get their size zeroed, so a zero size only
+                // counts as a match for the genuinely empty blob
+                let size_trusted = entry.stat.size != 0 || entry.id.is_empty_blob();
+                if fs_stat.matches(&entry.stat, self.stat_options) && size_trusted && !entry.racy {
+                    return false;
+                }
+                if entry.stat.size != 0 && fs_stat.size != entry.stat.size {
+                    return true;
+                }
+            }
+        }
 
-            let rel_path_bstr: &BStr = rel_path_str.into();
+        // too big to read: anything but fully matching, non-racy stat data is a change
+        if self.max_hash_size.is_some_and(|max| fs_meta.len() > max) {
+            return Stat::from_fs(fs_meta).map_or(true, |fs_stat| {
+                entry.racy || !fs_stat.matches(&entry.stat, self.stat_options)
+            });
+        }
 
-            if index.entry_by_path(rel_path_bstr).is_some() {
-                continue;
+        let Some(ref mut hasher) = self.hasher else {
+            return false;
+        };
+        let cache = self.cache.as_deref().zip(Stat::from_fs(fs_meta).ok());
+        if let Some((cache, fs_stat)) = cache {
+            if let Some(file_oid) = cache.get(entry.path.as_ref(), &fs_stat) {
+                return file_oid != entry.id;
             }
-
-            if path.is_dir() {
-                self.untracked_dir_stack.push(path);
-                continue;
+        }
+        match hasher.hash_file(&self.repo, &self.index, entry.path.as_ref(), full_path) {
+            Ok(file_oid) => {
+                if let Some((cache, fs_stat)) = cache {
+                    cache.insert(entry.path.as_ref(), fs_stat, file_oid);
+                }
+                file_oid != entry.id
             }
+            // like git, a file that can't be read or cleaned is taken as changed
+            Err(_) => true,
+        }
+    }
+
+    fn next_untracked(&mut self) -> Option<Result<FileStatus>> {
+        if let Some(result) = self.pending.pop_front() {
+            return Some(result);
+        }
+        if !std::mem::take(&mut self.untracked_walk) {
+            return None;
+        }
+        if let Err(e) = self.walk_untracked() {
+            return Some(Err(e));
+        }
+        self.pending.pop_front()
+    }
 
-            let metadata = if self.include_metadata {
-                match std::fs::metadata(&path) {
-                    Ok(m) => Some(FileMetadata {
+    /// Queue up the untracked and ignored paths `got-untracked` finds, with
+    /// metadata and nested repositories as the iterator reports them
+    fn walk_untracked(&mut self) -> Result<()> {
+        let start = match self.walk_options.subdir {
+            Some(ref subdir) => self.work_dir.join(subdir),
+            None => self.work_dir.clone(),
+        };
+        std::fs::metadata(&start).map_err(|e| Error::io(&start, e))?;
+        let index = self.repo.index_or_empty().map_err(Error::IndexCorrupt)?;
+        let found = got_untracked::untracked_files(&self.repo, &index, &self.walk_options)
+            .map_err(Error::git)?;
+
+        for found_path in found.paths {
+            let Some(dir_rel_path) = found_path.path.strip_suffix(b"/") else {
+                let path = crate::repo::worktree_path(&self.work_dir, found_path.path.as_bstr());
+                let metadata = if self.include_metadata {
+                    std::fs::symlink_metadata(&path).ok().map(|m| FileMetadata {
                         modified_time: m.modified().unwrap_or(std::time::SystemTime::UNIX_EPOCH),
                         size: m.len(),
-                    }),
-                    Err(_) => None,
-                }
-            } else {
-                None
+                        newest_modified_time: None,
+                    })
+                } else {
+                    None
+                };
+                self.pending
+                    .push_back(Ok(untracked_status(found_path, metadata)));
+                continue;
             };
 
-            let file_status = FileStatus {
-                path: rel_path_str.to_string(),
-                index_status: crate::types::StatusChar::None,
-                worktree_status: crate::types::StatusChar::Untracked,
-                metadata,
+            let dir_rel_path = dir_rel_path.as_bstr().to_owned();
+            let path = crate::repo::worktree_path(&self.work_dir, dir_rel_path.as_ref());
+            // an ignored directory is never recursed into, even as a repository
+            let nested_repo = !found_path.ignored && got_untracked::is_nested_repo(&path);
+            let (metadata, repos) = if found_path.ignored || nested_repo {
+                let metadata = if self.include_metadata {
+                    contents_span(&path)?
+                } else {
+                    None
+                };
+                let repos = if nested_repo && self.recurse_nested_repos {
+                    vec![dir_rel_path]
+                } else {
+                    Vec::new()
+                };
+                (metadata, repos)
+            } else if self.include_metadata || self.recurse_nested_repos {
+                self.untracked_dir_contents(dir_rel_path.as_ref())?
+            } else {
+                (None, Vec::new())
             };
+            self.pending.push_back(Ok(untracked_status(
+                found_path,
+                metadata.filter(|_| self.include_metadata),
+            )));
+            for repo_path in repos {
+                self.queue_nested_repo(repo_path.as_ref());
+            }
+        }
+        if self.recurse_nested_repos {
+            for repo_path in found.repos_matched_inside {
+                let repo_path = repo_path.strip_suffix(b"/").unwrap_or(&repo_path);
+                self.queue_nested_repo(repo_path.as_bstr());
+            }
+        }
+        Ok(())
+    }
 
-            if !self.should_filter_out_untracked(&file_status) {
-                return Some(Ok(file_status));
+    /// The span of the untracked files in the untracked directory `rel_path`,
+    /// and the repositories nested in it
+    fn untracked_dir_contents(
+        &mut self,
+        rel_path: &BStr,
+    ) -> Result<(Option<FileMetadata>, Vec<BString>)> {
+        let index = self.repo.index_or_empty().map_err(Error::IndexCorrupt)?;
+        let options = got_untracked::StatusOptions {
+            untracked: UntrackedFilter::All,
+            ignored: IgnoredFilter::No,
+            subdir: Some(gix::path::from_bstr(rel_path)?.into_owned()),
+            pathspec: Vec::new(),
+            keep_index: self.walk_options.keep_index,
+        };
+        let found =
+            got_untracked::untracked_files(&self.repo, &index, &options).map_err(Error::git)?;
+
+        let mut span = None;
+        let mut repos = Vec::new();
+        for found_path in found.paths {
+            match found_path.path.strip_suffix(b"/") {
+                Some(repo_path) => {
+                    let path = crate::repo::worktree_path(&self.work_dir, repo_path.as_bstr());
+                    let repo_span = if self.include_metadata {
+                        contents_span(&path)?
+                    } else {
+                        Some(file_span(&path))
+                    };
+                    if let Some(ref repo_span) = repo_span {
+                        widen(&mut span, repo_span);
+                    }
+                    if self.recurse_nested_repos {
+                        repos.push(repo_path.as_bstr().to_owned());
+                    }
+                }
+                None => {
+                    let path =
+                        crate::repo::worktree_path(&self.work_dir, found_path.path.as_bstr());
+                    widen(&mut span, &file_span(&path));
+                }
             }
         }
+        Ok((span, repos))
     }
 
-    fn should_filter_out_untracked(&self, status: &FileStatus) -> bool {
-        if let Some(ref filter) = self.status_filter {
-            !filter.contains(&status.index_status) && !filter.contains(&status.worktree_status)
-        } else {
-            false
+    /// Rename detection needs every deletion and addition at hand, and ordered
+    /// parallel output every worker's results, so the first call gathers them all
+    fn next_collected(&mut self) -> Option<Result<FileStatus>> {
+        if self.collected.is_none() {
+            let results: Vec<Result<FileStatus>> = match self.parallel.take() {
+                Some(parallel) => parallel.collect(),
+                None => std::iter::from_fn(|| self.next_unpaired()).collect(),
+            };
+            let mut statuses = Vec::new();
+            let mut errors = Vec::new();
+            for result in results {
+                match result {
+                    Ok(status) => statuses.push(status),
+                    Err(e) => errors.push(Err(e)),
+                }
+            }
+            let mut results: Vec<Result<FileStatus>> = match crate::rename::detect(
+                &self.repo,
+                &self.work_dir,
+                self.base_tree_id,
+                statuses,
+                &self.renames,
+            ) {
+                Ok(statuses) => statuses.into_iter().map(Ok).collect(),
+                Err(e) => vec![Err(e)],
+            };
+            results.extend(errors);
+            self.collected = Some(results.into_iter());
         }
+        self.collected.as_mut().and_then(Iterator::next)
     }
-}
 
-impl Iterator for RepoIter {
-    type Item = Result<FileStatus>;
+    /// Every untracked and ignored path at once, sorted the way ordered
+    /// parallel output has them, errors after
+    fn gather_untracked(&mut self) {
+        let mut statuses = Vec::new();
+        let mut errors = Vec::new();
+        while let Some(result) = self.next_untracked() {
+            match result {
+                Ok(status) => statuses.push(status),
+                Err(e) => errors.push(Err(e)),
+            }
+        }
+        sort_untracked(&mut statuses);
+        self.pending = statuses.into_iter().map(Ok).chain(errors).collect();
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
+    fn next_unpaired(&mut self) -> Option<Result<FileStatus>> {
         loop {
             match self.phase {
                 IterationPhase::Tracked => {
-                    if self.mode == IterMode::Untracked {
-                        self.phase = IterationPhase::Untracked;
-                        continue;
-                    }
-
-                    if let Some(result) = self.next_tracked() {
-                        return Some(result);
-                    }
+                    if self.mode != IterMode::Untracked {
+                        if let Some(result) = self.next_tracked() {
+                            return Some(result);
+                        }
 
-                    if self.mode == IterMode::Tracked {
-                        return None;
+                        if self.mode == IterMode::Tracked {
+                            return None;
+                        }
                     }
 
+                    self.gather_untracked();
                     self.phase = IterationPhase::Untracked;
                 }
-                IterationPhase::Untracked => {
-                    if let Some(result) = self.next_untracked() {
-                        return Some(result);
-                    }
-                    return None;
-                }
+                IterationPhase::Untracked => return self.pending.pop_front(),
+            }
+        }
+    }
+}
+
+impl Iterator for RepoIter {
+    type Item = Result<FileStatus>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let streaming = self.renames.detection == RenameDetection::Off;
+            let result = match self.parallel {
+                Some(ref parallel) if streaming && !parallel.is_ordered() => parallel.recv()?,
+                None if streaming => self.next_unpaired()?,
+                _ => self.next_collected()?,
+            };
+
+            match result {
+                Ok(status) if self.should_filter_out(&status) => continue,
+                result => return Some(result),
             }
         }
     }
 }
+
+/// Grow `span` to cover `metadata`: oldest and newest modification time, total size
+fn widen(span: &mut Option<FileMetadata>, metadata: &FileMetadata) {
+    let newest = metadata
+        .newest_modified_time
+        .unwrap_or(metadata.modified_time);
+    match span {
+        Some(span) => {
+            span.modified_time = span.modified_time.min(metadata.modified_time);
+            span.newest_modified_time = span.newest_modified_time.max(Some(newest));
+            span.size += metadata.size;
+        }
+        None => {
+            *span = Some(FileMetadata {
+                modified_time: metadata.modified_time,
+                size: metadata.size,
+                newest_modified_time: Some(newest),
+            })
+        }
+    }
+}
+
+/// A single file's metadata, as a span of one
+fn file_span(path: &Path) -> FileMetadata {
+    let (modified_time, size) = match std::fs::metadata(path) {
+        Ok(m) => (
+            m.modified().unwrap_or(std::time::SystemTime::UNIX_EPOCH),
+            m.len(),
+        ),
+        Err(_) => (std::time::SystemTime::UNIX_EPOCH, 0),
+    };
+    FileMetadata {
+        modified_time,
+        size,
+        newest_modified_time: Some(modified_time),
+    }
+}
+
+/// Every file below `dir`, leaving out `.git`
+fn for_each_file(dir: &Path, f: &mut impl FnMut(&Path)) -> Result<()> {
+    for entry in std::fs::read_dir(dir).map_err(|e| Error::io(dir, e))? {
+        let Ok(entry) = entry else { continue };
+        if entry.file_name() == ".git" {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            for_each_file(&path, f)?;
+        } else {
+            f(&path);
+        }
+    }
+    Ok(())
+}
+
+/// Span of every file below `dir`, nested repositories included
+fn contents_span(dir: &Path) -> Result<Option<FileMetadata>> {
+    let mut span = None;
+    for_each_file(dir, &mut |file| widen(&mut span, &file_span(file)))?;
+    Ok(span)
+}
+
+/// An untracked or ignored path from `got-untracked`, as a status
+fn untracked_status(
+    found: got_untracked::UntrackedPath,
+    metadata: Option<FileMetadata>,
+) -> FileStatus {
+    FileStatus {
+        path: found.path,
+        orig_path: None,
+        index_status: StatusChar::None,
+        worktree_status: if found.ignored {
+            StatusChar::Ignored
+        } else {
+            StatusChar::Untracked
+        },
+        metadata,
+        conflict: None,
+        modes: None,
+        submodule: None,
+    }
+}
+
+/// Untracked paths in path order, ignored ones after them
+pub(crate) fn sort_untracked(statuses: &mut [FileStatus]) {
+    let ignored = |status: &FileStatus| status.worktree_status == StatusChar::Ignored;
+    statuses.sort_by(|a, b| (ignored(a), &a.path).cmp(&(ignored(b), &b.path)));
+}
+
+/// Move a status from a nested repository's paths to the outer one's
+fn prefix_paths(status: &mut FileStatus, prefix: &BStr) {
+    status.map_paths(|path| {
+        let mut prefixed = dir_path(prefix);
+        prefixed.extend_from_slice(path);
+        prefixed
+    });
+}
+
+/// `dir/`, the way git reports a directory as a whole
+fn dir_path(rel_path: &BStr) -> BString {
+    let mut path = rel_path.to_owned();
+    path.push_byte(b'/');
+    path
+}
+
+/// Symlinks, gitlinks and files are different types; the executable bit is just a mode change
+fn is_type_change(a: Mode, b: Mode) -> bool {
+    let kind = |mode: Mode| {
+        if mode == Mode::FILE_EXECUTABLE {
+            Mode::FILE
+        } else {
+            mode
+        }
+    };
+    kind(a) != kind(b)
+}
+
+/// Paths of base tree blobs that have no index entry
+fn head_only_paths(
+    base_tree: &gix::Tree<'_>,
+    tracked: impl Fn(&BStr) -> bool,
+    subdir: Option<&Path>,
+    mut pathspec: Option<&mut gix::PathspecDetached>,
+) -> Result<Vec<(BString, Mode)>> {
+    let paths = base_tree
+        .traverse()
+        .breadthfirst
+        .files()?
+        .into_iter()
+        .filter(|entry| !entry.mode.is_tree())
+        .filter(|entry| in_subdir(entry.filepath.as_ref(), subdir))
+        .filter(|entry| {
+            pathspec
+                .as_mut()
+                .is_none_or(|spec| spec.is_included(entry.filepath.as_bstr(), Some(false)))
+        })
+        .filter(|entry| !tracked(entry.filepath.as_ref()))
+        .map(|entry| (entry.filepath, Mode::from(entry.mode)))
+        .collect();
+
+    Ok(paths)
+}
+
+/// Files under a sparse directory entry `dir/` whose tree differs from the base
+/// tree's: `Some(id)` for each one in the index, `None` for base tree files it
+/// dropped. `None` overall when the trees match, as they do for any directory
+/// outside the cone that hasn't been touched.
+#[allow(clippy::type_complexity)]
+fn sparse_dir_changes(
+    repo: &Repository,
+    base_tree: &gix::Tree<'_>,
+    dir: &BStr,
+    id: ObjectId,
+) -> Result<Option<Vec<(BString, Option<ObjectId>, Mode)>>> {
+    let name = &dir[..dir.len() - 1];
+    let base_dir = match base_tree.lookup_entry(name.split(|&b| b == b'/'))? {
+        Some(entry) if entry.mode().is_tree() && entry.object_id() == id => return Ok(None),
+        Some(entry) if entry.mode().is_tree() => Some(entry.object_id()),
+        _ => None,
+    };
+
+    let files = |tree_id: ObjectId| -> Result<Vec<(BString, ObjectId, Mode)>> {
+        let entries = repo
+            .find_tree(tree_id)
+            .map_err(Error::object(tree_id))?
+            .traverse()
+            .breadthfirst
+            .files()?;
+        Ok(entries
+            .into_iter()
+            .filter(|entry| !entry.mode.is_tree())
+            .map(|entry| {
+                let mut path = dir.to_owned();
+                path.push_str(&entry.filepath);
+                (path, entry.oid, Mode::from(entry.mode))
+            })
+            .collect())
+    };
+    let mut changes: Vec<_> = files(id)?
+        .into_iter()
+        .map(|(path, id, mode)| (path, Some(id), mode))
+        .collect();
+    if let Some(base_dir) = base_dir {
+        let in_index: HashSet<BString> = changes.iter().map(|(path, ..)| path.clone()).collect();
+        changes.extend(
+            files(base_dir)?
+                .into_iter()
+                .filter(|(path, ..)| !in_index.contains(path))
+                .map(|(path, _, mode)| (path, None, mode)),
+        );
+    }
+    Ok(Some(changes))
+}
+
+/// Whole path components only, so `crate/got` doesn't take in `crate/gotconfig`
+fn in_subdir(path: &BStr, subdir: Option<&Path>) -> bool {
+    subdir
+        .is_none_or(|subdir| gix::path::from_bstr(path).is_ok_and(|path| path.starts_with(subdir)))
+}
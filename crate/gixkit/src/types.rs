@@ -1,3 +1,7 @@
+use gix::bstr::{BStr, BString, ByteVec};
+use gix::index::entry::Mode;
+use gix_hash::ObjectId;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StatusChar {
     Modified,
@@ -5,6 +9,7 @@ pub enum StatusChar {
     Deleted,
     Renamed,
     Copied,
+    TypeChanged,
     Unmerged,
     Untracked,
     Ignored,
@@ -19,6 +24,7 @@ impl From<StatusChar> for char {
             StatusChar::Deleted => 'D',
             StatusChar::Renamed => 'R',
             StatusChar::Copied => 'C',
+            StatusChar::TypeChanged => 'T',
             StatusChar::Unmerged => 'U',
             StatusChar::Untracked => '?',
             StatusChar::Ignored => '!',
@@ -35,6 +41,7 @@ impl StatusChar {
             'D' => StatusChar::Deleted,
             'R' => StatusChar::Renamed,
             'C' => StatusChar::Copied,
+            'T' => StatusChar::TypeChanged,
             'U' => StatusChar::Unmerged,
             '?' => StatusChar::Untracked,
             '!' => StatusChar::Ignored,
@@ -44,26 +51,77 @@ impl StatusChar {
     }
 }
 
+/// Untracked discovery lives in `got-untracked`, which `RepoIter` hands it to
+pub use got_untracked::{configured_untracked, IgnoredFilter, UntrackedFilter};
+
 #[derive(Debug, Clone, Copy, Default, PartialEq)]
-pub enum UntrackedFilter {
-    No,
+pub enum RenameDetection {
+    Off,
     #[default]
-    Normal,
-    All,
+    Renames,
+    Copies,
+}
+
+/// Blob ids of an unmerged path's index stages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConflictStages {
+    pub base: Option<ObjectId>,
+    pub ours: Option<ObjectId>,
+    pub theirs: Option<ObjectId>,
+}
+
+/// Modes of a tracked path, as in `git status --porcelain=v2`; `None` where the
+/// path is absent. Gitlinks (submodules) are `Mode::COMMIT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileModes {
+    /// Mode in the base tree: HEAD's, or the revision given to `base`
+    pub head: Option<Mode>,
+    pub index: Option<Mode>,
+    pub worktree: Option<Mode>,
+}
+
+/// What differs in a checked-out submodule, as in `git status --porcelain=v2`'s
+/// `S<c><m><u>` field
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubmoduleState {
+    /// HEAD of the submodule isn't the commit recorded in the superproject
+    pub new_commits: bool,
+    /// Tracked files of the submodule have staged or unstaged changes
+    pub modified_content: bool,
+    pub untracked_content: bool,
+}
+
+impl SubmoduleState {
+    pub fn is_dirty(&self) -> bool {
+        self.new_commits || self.modified_content || self.untracked_content
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct FileMetadata {
     pub modified_time: std::time::SystemTime,
     pub size: u64,
+    /// Set for a `dir/` entry (a collapsed untracked or ignored directory),
+    /// where `modified_time` is the oldest of its files', this the newest and
+    /// `size` their total
+    pub newest_modified_time: Option<std::time::SystemTime>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct FileStatus {
-    pub path: String,
+    /// Repository-relative path, as the raw bytes git stores
+    pub path: BString,
+    /// Source path when the status is `Renamed` or `Copied`
+    pub orig_path: Option<BString>,
     pub index_status: StatusChar,
     pub worktree_status: StatusChar,
     pub metadata: Option<FileMetadata>,
+    /// Present for unmerged paths during a merge, rebase or cherry-pick
+    pub conflict: Option<ConflictStages>,
+    /// Present for tracked paths
+    pub modes: Option<FileModes>,
+    /// Present for gitlinks whose submodule is checked out
+    pub submodule: Option<SubmoduleState>,
 }
 
 impl FileStatus {
@@ -78,4 +136,78 @@ impl FileStatus {
     pub fn is_worktree_modified(&self) -> bool {
         self.worktree_status != StatusChar::None
     }
+
+    /// The `XY` columns of `git status --short`; untracked and ignored paths
+    /// fill both, `??` and `!!`
+    pub fn xy(&self) -> (StatusChar, StatusChar) {
+        match self.worktree_status {
+            StatusChar::Untracked | StatusChar::Ignored => {
+                (self.worktree_status, self.worktree_status)
+            }
+            _ => (self.index_status, self.worktree_status),
+        }
+    }
+
+    pub fn is_conflicted(&self) -> bool {
+        self.conflict.is_some()
+    }
+
+    pub fn is_gitlink(&self) -> bool {
+        self.modes.is_some_and(|modes| {
+            [modes.head, modes.index, modes.worktree]
+                .into_iter()
+                .flatten()
+                .any(|mode| mode.is_submodule())
+        })
+    }
+
+    /// Executable bit flipped between HEAD and the index or the index and the worktree
+    pub fn mode_changed(&self) -> bool {
+        self.modes.is_some_and(|modes| {
+            matches!((modes.head, modes.index), (Some(a), Some(b)) if a != b)
+                || matches!((modes.index, modes.worktree), (Some(a), Some(b)) if a != b)
+        })
+    }
+
+    /// `old -> new` for renames and copies, the plain path otherwise, each side
+    /// quoted like `git status` does (see [`crate::quote_path`])
+    pub fn display_path(&self, quote_non_ascii: bool) -> BString {
+        let mut display = BString::default();
+        if let Some(ref orig_path) = self.orig_path {
+            display.push_str(crate::quote_path(orig_path.as_ref(), quote_non_ascii));
+            display.push_str(" -> ");
+        }
+        display.push_str(crate::quote_path(self.path.as_ref(), quote_non_ascii));
+        display
+    }
+
+    /// Rewrite `path` and `orig_path` alike, e.g. with [`crate::relative_path`]
+    pub fn map_paths(&mut self, mut f: impl FnMut(&BStr) -> BString) {
+        for path in std::iter::once(&mut self.path).chain(self.orig_path.as_mut()) {
+            *path = f(path.as_ref());
+        }
+    }
+}
+
+/// One checkout of a repository, as `git worktree list` shows it
+#[derive(Debug, Clone)]
+pub struct WorktreeInfo {
+    /// Root directory of the checkout
+    pub path: std::path::PathBuf,
+    /// The repository's own worktree rather than one added with `git worktree add`
+    pub is_main: bool,
+    /// Checked-out commit, `None` on an unborn branch
+    pub head: Option<ObjectId>,
+    /// Checked-out branch (`main`), `None` when HEAD is detached
+    pub branch: Option<BString>,
+    pub locked: bool,
+    /// The directory is gone; `git worktree prune` would drop it
+    pub prunable: bool,
+}
+
+impl WorktreeInfo {
+    /// Open the checkout, for a status run of its own
+    pub fn open(&self) -> crate::Result<gix::Repository> {
+        crate::open_repo(&self.path)
+    }
 }
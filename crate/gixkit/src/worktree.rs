@@ -0,0 +1,155 @@
+use crate::{Error, Result};
+use gix::filter::plumbing::{pipeline::convert::ToGitOutcome, Pipeline};
+use gix::objs::Find;
+use gix::objs::Kind;
+use gix::{bstr::BStr, Repository};
+use gix_hash::ObjectId;
+use std::io::{Read, Seek};
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+
+/// Hashes worktree files as `git add` would store them: `.gitattributes` are
+/// looked up per path, then `text`/`eol`/`core.autocrlf` conversion, `ident`,
+/// `working-tree-encoding` and external clean filter drivers run before hashing.
+pub(crate) struct WorktreeHasher {
+    pipeline: Pipeline,
+    attributes: gix::worktree::Stack,
+    object_hash: gix::hash::Kind,
+}
+
+impl WorktreeHasher {
+    pub fn new(repo: &Repository) -> Result<Self> {
+        let (pipeline, _index) = repo.filter_pipeline(None)?;
+        let (pipeline, attributes) = pipeline.into_parts();
+        Ok(Self {
+            pipeline,
+            attributes,
+            object_hash: repo.object_hash(),
+        })
+    }
+
+    /// Blob id of the worktree file at `full_path`, known to git as `rela_path`.
+    /// Files that need no conversion are streamed through the hasher, and clean
+    /// filter output through a temporary file, so memory use doesn't grow with
+    /// the file size.
+    pub fn hash_file(
+        &mut self,
+        repo: &Repository,
+        index: &gix::index::State,
+        rela_path: &BStr,
+        full_path: &Path,
+    ) -> Result<ObjectId> {
+        let io = |e| Error::io(full_path, e);
+        if std::fs::symlink_metadata(full_path)
+            .map_err(io)?
+            .is_symlink()
+        {
+            let content = self.read_file(repo, index, rela_path, full_path)?;
+            return self.hash_blob(&content);
+        }
+
+        let file = std::fs::File::open(full_path).map_err(io)?;
+        let len = file.metadata().map_err(io)?.len();
+        let object_hash = self.object_hash;
+        match self.convert(repo, index, rela_path, file)? {
+            ToGitOutcome::Unchanged(mut file) => Ok(gix::objs::compute_stream_hash(
+                object_hash,
+                Kind::Blob,
+                &mut file,
+                len,
+                &mut gix::progress::Discard,
+                &AtomicBool::new(false),
+            )?),
+            ToGitOutcome::Buffer(buf) => Ok(gix::objs::compute_hash(object_hash, Kind::Blob, buf)?),
+            // the filtered size is only known at the end of a clean filter's
+            // output, so it goes to a temporary file ahead of the hasher
+            ToGitOutcome::Process(mut stream) => {
+                let temp_io = |e| Error::io(std::env::temp_dir(), e);
+                let mut spool = tempfile::tempfile().map_err(temp_io)?;
+                let len = std::io::copy(&mut stream, &mut spool).map_err(io)?;
+                spool.rewind().map_err(temp_io)?;
+                Ok(gix::objs::compute_stream_hash(
+                    object_hash,
+                    Kind::Blob,
+                    &mut spool,
+                    len,
+                    &mut gix::progress::Discard,
+                    &AtomicBool::new(false),
+                )?)
+            }
+        }
+    }
+
+    /// Blob id of already converted content, in the repository's object format
+    pub fn hash_blob(&self, content: &[u8]) -> Result<ObjectId> {
+        Ok(gix::objs::compute_hash(
+            self.object_hash,
+            Kind::Blob,
+            content,
+        )?)
+    }
+
+    /// Worktree file contents after the convert-to-git pipeline, or the target of a symlink
+    pub fn read_file(
+        &mut self,
+        repo: &Repository,
+        index: &gix::index::State,
+        rela_path: &BStr,
+        full_path: &Path,
+    ) -> Result<Vec<u8>> {
+        let io = |e| Error::io(full_path, e);
+        // git stores a symlink as a blob of its target path, unfiltered
+        if std::fs::symlink_metadata(full_path)
+            .map_err(io)?
+            .is_symlink()
+        {
+            let target = std::fs::read_link(full_path).map_err(io)?;
+            return Ok(gix::path::into_bstr(target)?.into_owned().into());
+        }
+
+        let file = std::fs::File::open(full_path).map_err(io)?;
+        let mut content = Vec::new();
+        match self.convert(repo, index, rela_path, file)? {
+            ToGitOutcome::Unchanged(mut file) => {
+                file.read_to_end(&mut content).map_err(io)?;
+            }
+            ToGitOutcome::Process(mut stream) => {
+                stream.read_to_end(&mut content).map_err(io)?;
+            }
+            ToGitOutcome::Buffer(buf) => content.extend_from_slice(buf),
+        }
+        Ok(content)
+    }
+
+    /// Run `file` through the attributes-driven convert-to-git pipeline
+    fn convert<'a>(
+        &'a mut self,
+        repo: &Repository,
+        index: &gix::index::State,
+        rela_path: &BStr,
+        file: std::fs::File,
+    ) -> Result<ToGitOutcome<'a, std::fs::File>> {
+        let rela_path_os = gix::path::from_bstr(rela_path)?;
+        let attributes = self
+            .attributes
+            .at_path(rela_path_os.as_ref(), None, &repo.objects)
+            .map_err(|e| Error::io(rela_path_os.as_ref(), e))?;
+
+        let outcome = self.pipeline.convert_to_git(
+            file,
+            rela_path_os.as_ref(),
+            &mut |_, attrs| {
+                attributes.matching_attributes(attrs);
+            },
+            // `text=auto` keeps CRLF in files that were committed with CRLF
+            &mut |buf| {
+                let Some(entry) = index.entry_by_path(rela_path) else {
+                    return Ok(None);
+                };
+                let obj = repo.objects.try_find(&entry.id, buf)?;
+                Ok(obj.filter(|obj| obj.kind == Kind::Blob).map(|_| ()))
+            },
+        )?;
+        Ok(outcome)
+    }
+}
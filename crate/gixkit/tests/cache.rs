@@ -0,0 +1,75 @@
+//! The status cache drops blob ids that no longer hold
+
+mod common;
+
+use common::{git_porcelain, porcelain, Repo};
+use gixkit::IterMode;
+
+fn cached_status(repo: &Repo) -> Vec<String> {
+    let statuses = repo
+        .builder()
+        .mode(IterMode::Tracked)
+        .status_cache(true)
+        .build()
+        .unwrap();
+    porcelain(statuses)
+}
+
+/// `sub/file` committed as it is, and aged so that it has to be hashed
+fn filtered_repo(clean: &str) -> Repo {
+    let repo = Repo::init(&[]);
+    repo.git(&["config", "filter.upper.clean", clean]);
+    repo.write("sub/file", "abc\n");
+    repo.commit_all("initial");
+    repo.age("sub/file", 3600);
+    repo
+}
+
+#[test]
+fn nested_gitattributes_change_drops_entries_below_it() {
+    let repo = filtered_repo("tr a-z A-Z");
+    assert_eq!(cached_status(&repo), Vec::<String>::new());
+
+    repo.write("sub/.gitattributes", "file filter=upper\n");
+    assert_eq!(cached_status(&repo), [" M sub/file"]);
+    assert_eq!(git_porcelain(&repo, &["-uno"]), [" M sub/file"]);
+}
+
+#[test]
+fn filter_driver_change_drops_everything() {
+    let repo = filtered_repo("cat");
+    repo.write(".gitattributes", "file filter=upper\n");
+    repo.commit_all("attributes");
+    repo.age("sub/file", 7200);
+    assert_eq!(cached_status(&repo), Vec::<String>::new());
+
+    repo.git(&["config", "filter.upper.clean", "tr a-z A-Z"]);
+    assert_eq!(cached_status(&repo), [" M sub/file"]);
+    assert_eq!(git_porcelain(&repo, &["-uno"]), [" M sub/file"]);
+}
+
+/// Writing the untracked cache into the index leaves its entries, and with them
+/// the cached blob ids, as they were
+#[test]
+fn untracked_cache_write_keeps_the_cache() {
+    let repo = Repo::init(&[]);
+    let log = repo.path().join(".git").join("clean-log");
+    let clean = format!("echo >>'{}'; cat", log.display());
+    repo.git(&["config", "filter.log.clean", &clean]);
+    repo.git(&["config", "core.untrackedCache", "true"]);
+    repo.write(".gitattributes", "file filter=log\n");
+    repo.write("file", "content\n");
+    repo.commit_all("initial");
+    repo.age("file", 3600);
+    let _ = std::fs::remove_file(&log);
+    let cleaned = || std::fs::read_to_string(&log).map_or(0, |log| log.lines().count());
+
+    let status = || porcelain(repo.builder().status_cache(true).build().unwrap());
+    assert_eq!(status(), Vec::<String>::new());
+    assert_eq!(cleaned(), 1);
+    let index = std::fs::read(repo.file(".git/index")).unwrap();
+    assert!(index.windows(4).any(|window| window == b"UNTR"));
+
+    assert_eq!(status(), Vec::<String>::new());
+    assert_eq!(cleaned(), 1);
+}
@@ -0,0 +1,133 @@
+//! Repositories made by the `git` binary in temporary directories, for checking
+//! gixkit against what git itself reports
+
+#![allow(dead_code)]
+
+use gixkit::{FileStatus, RepoIterBuilder};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+pub struct Repo {
+    dir: tempfile::TempDir,
+}
+
+impl Repo {
+    /// `git init` with `args`, e.g. `--object-format=sha256`
+    pub fn init(args: &[&str]) -> Self {
+        let dir = tempfile::tempdir().expect("temporary directory");
+        let repo = Repo { dir };
+        repo.git(&[&["init", "-q"], args].concat());
+        repo
+    }
+
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Run git in the worktree, without the user's or the system's config;
+    /// its stdout
+    pub fn git(&self, args: &[&str]) -> String {
+        self.git_with_input(args, "")
+    }
+
+    /// [`Repo::git`], with `input` on its stdin
+    pub fn git_with_input(&self, args: &[&str], input: &str) -> String {
+        let mut child = Command::new("git")
+            .args(args)
+            .current_dir(self.path())
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_CONFIG_GLOBAL", "/dev/null")
+            .env("GIT_AUTHOR_NAME", "A U Thor")
+            .env("GIT_AUTHOR_EMAIL", "author@example.com")
+            .env("GIT_COMMITTER_NAME", "C O Mitter")
+            .env("GIT_COMMITTER_EMAIL", "committer@example.com")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("git runs");
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(input.as_bytes()).unwrap();
+        drop(stdin);
+        let output = child.wait_with_output().unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).expect("UTF-8 output")
+    }
+
+    pub fn write(&self, path: &str, content: impl AsRef<[u8]>) {
+        let path = self.file(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    pub fn file(&self, path: &str) -> PathBuf {
+        self.path().join(path)
+    }
+
+    pub fn commit_all(&self, message: &str) {
+        self.git(&["add", "-A"]);
+        self.git(&["commit", "-q", "-m", message]);
+    }
+
+    pub fn open(&self) -> gix::Repository {
+        gixkit::open_repo(self.path()).expect("repository opens")
+    }
+
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub fn builder(&self) -> RepoIterBuilder {
+        RepoIterBuilder::new(Arc::new(self.open()))
+    }
+
+    /// Set the modification time of `path` `secs` into the past, so that its
+    /// stat data no longer matches the index and only its content can tell
+    pub fn age(&self, path: &str, secs: u64) {
+        let mtime = std::time::SystemTime::now() - std::time::Duration::from_secs(secs);
+        self.set_mtime(path, mtime);
+    }
+
+    /// Set the modification time of `path`, a file in the worktree or the git dir
+    pub fn set_mtime(&self, path: &str, mtime: std::time::SystemTime) {
+        let file = std::fs::File::options()
+            .write(true)
+            .open(self.file(path))
+            .unwrap();
+        file.set_modified(mtime).unwrap();
+    }
+}
+
+/// `XY path` lines, sorted, for comparing with `git status --porcelain`
+pub fn porcelain(statuses: impl IntoIterator<Item = gixkit::Result<FileStatus>>) -> Vec<String> {
+    let mut lines: Vec<String> = statuses
+        .into_iter()
+        .map(|status| {
+            let status = status.expect("status of a path");
+            let (x, y) = status.xy();
+            format!(
+                "{}{} {}",
+                char::from(x),
+                char::from(y),
+                status.display_path(true)
+            )
+        })
+        .collect();
+    lines.sort();
+    lines
+}
+
+/// `git status --porcelain` with `args`, sorted
+pub fn git_porcelain(repo: &Repo, args: &[&str]) -> Vec<String> {
+    let mut lines: Vec<String> = repo
+        .git(&[&["status", "--porcelain"], args].concat())
+        .lines()
+        .map(str::to_owned)
+        .collect();
+    lines.sort();
+    lines
+}
@@ -0,0 +1,118 @@
+//! `discover_repo` against where git finds the repository from a directory
+
+mod common;
+
+use common::Repo;
+use gixkit::{discover_repo, Error};
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+
+/// Tests setting `GIT_*` variables take turns, the environment being the
+/// process's
+static ENV: Mutex<()> = Mutex::new(());
+
+/// `vars` set until dropped
+struct EnvVars(Vec<&'static str>);
+
+impl EnvVars {
+    fn set(vars: &[(&'static str, &std::ffi::OsStr)]) -> Self {
+        for (name, value) in vars {
+            std::env::set_var(name, value);
+        }
+        EnvVars(vars.iter().map(|(name, _)| *name).collect())
+    }
+}
+
+impl Drop for EnvVars {
+    fn drop(&mut self) {
+        for name in &self.0 {
+            std::env::remove_var(name);
+        }
+    }
+}
+
+/// Whether `git rev-parse` finds a repository from `dir`, with the current
+/// environment
+fn git_finds(dir: &Path) -> bool {
+    Command::new("git")
+        .args(["rev-parse", "--git-dir"])
+        .current_dir(dir)
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+        .output()
+        .unwrap()
+        .status
+        .success()
+}
+
+fn canonical(path: &Path) -> std::path::PathBuf {
+    path.canonicalize().unwrap()
+}
+
+#[test]
+fn found_from_a_subdirectory() {
+    let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+    let repo = Repo::init(&[]);
+    repo.write("a/b/file", "1\n");
+
+    let found = discover_repo(repo.file("a/b")).unwrap();
+    assert_eq!(canonical(found.workdir().unwrap()), canonical(repo.path()));
+    assert_eq!(canonical(found.git_dir()), canonical(&repo.file(".git")));
+}
+
+#[test]
+fn git_dir_and_work_tree_come_from_the_environment() {
+    let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+    let repo = Repo::init(&[]);
+    let elsewhere = tempfile::tempdir().unwrap();
+    let git_dir = elsewhere.path().join("repo.git");
+    std::fs::rename(repo.file(".git"), &git_dir).unwrap();
+    let unrelated = tempfile::tempdir().unwrap();
+
+    let _vars = EnvVars::set(&[
+        ("GIT_DIR", git_dir.as_os_str()),
+        ("GIT_WORK_TREE", repo.path().as_os_str()),
+    ]);
+    assert!(git_finds(unrelated.path()));
+    let found = discover_repo(unrelated.path()).unwrap();
+    assert_eq!(canonical(found.git_dir()), canonical(&git_dir));
+    assert_eq!(canonical(found.workdir().unwrap()), canonical(repo.path()));
+}
+
+/// git never moves up into a ceiling directory; one it starts in doesn't
+/// count
+#[test]
+fn ceiling_directories_stop_the_search_as_in_git() {
+    let _env = ENV.lock().unwrap_or_else(|e| e.into_inner());
+    let repo = Repo::init(&[]);
+    repo.write("a/b/file", "1\n");
+    let unrelated = tempfile::tempdir().unwrap();
+
+    let cases = [
+        ("a", "a/b", false),
+        ("", "a", false),
+        ("", "", true),
+        ("a/b", "a/b", true),
+    ];
+    for (ceiling, start, found) in cases {
+        let ceiling = repo.file(ceiling);
+        let _vars = EnvVars::set(&[("GIT_CEILING_DIRECTORIES", ceiling.as_os_str())]);
+        let start = repo.file(start);
+        assert_eq!(git_finds(&start), found, "git, {ceiling:?} {start:?}");
+        match discover_repo(&start) {
+            Ok(_) => assert!(found, "found past {ceiling:?} from {start:?}"),
+            Err(Error::NotARepository { .. }) => {
+                assert!(!found, "not found under {ceiling:?} from {start:?}")
+            }
+            Err(e) => panic!("{e}"),
+        }
+    }
+
+    // a ceiling elsewhere, or a relative one, changes nothing
+    for ceiling in [unrelated.path(), Path::new("a")] {
+        let _vars = EnvVars::set(&[("GIT_CEILING_DIRECTORIES", ceiling.as_os_str())]);
+        assert!(git_finds(&repo.file("a/b")));
+        assert!(discover_repo(repo.file("a/b")).is_ok(), "{ceiling:?}");
+    }
+}
@@ -0,0 +1,41 @@
+//! The `Error` variants callers match on
+
+mod common;
+
+use common::Repo;
+use gixkit::{discover_repo, open_repo, Error};
+
+#[test]
+fn no_repository_is_not_a_repository_from_either_entry_point() {
+    let dir = tempfile::tempdir().unwrap();
+    assert!(matches!(
+        open_repo(dir.path()),
+        Err(Error::NotARepository { .. })
+    ));
+    assert!(matches!(
+        discover_repo(dir.path()),
+        Err(Error::NotARepository { .. })
+    ));
+}
+
+#[test]
+fn repository_that_cannot_be_opened_is_open() {
+    let repo = Repo::init(&[]);
+    repo.git(&["config", "core.repositoryFormatVersion", "99"]);
+    match open_repo(repo.path()) {
+        Err(Error::Open { path, .. }) => assert_eq!(path, repo.path()),
+        other => panic!("expected Error::Open, got {other:?}"),
+    }
+}
+
+#[test]
+fn corrupt_index_is_index_corrupt() {
+    let repo = Repo::init(&[]);
+    repo.write("file", "content\n");
+    repo.commit_all("initial");
+    repo.write(".git/index", "DIRC but nothing after it");
+    assert!(matches!(
+        repo.builder().build(),
+        Err(Error::IndexCorrupt(_))
+    ));
+}
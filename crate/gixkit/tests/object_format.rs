@@ -0,0 +1,130 @@
+//! Status in SHA-1 and SHA-256 repositories alike
+
+mod common;
+
+use common::{git_porcelain, porcelain, Repo};
+use gix::hash::Kind;
+use gixkit::{get_head_tree, IterMode, UntrackedFilter};
+
+const FORMATS: [(Kind, &str); 2] = [
+    (Kind::Sha1, "--object-format=sha1"),
+    (Kind::Sha256, "--object-format=sha256"),
+];
+
+/// Every kind of change there is against HEAD, staged and not
+fn changed_repo(format: &str) -> Repo {
+    let repo = Repo::init(&[format]);
+    repo.write("modified", "one\n");
+    repo.write("renamed", "a\nfew\nlines\nto\nmatch\n");
+    repo.write("dir/deleted", "gone\n");
+    repo.write("staged", "before\n");
+    repo.write("touched", "same\n");
+    repo.commit_all("initial");
+
+    repo.write("modified", "two\n");
+    repo.git(&["mv", "renamed", "moved"]);
+    std::fs::remove_file(repo.file("dir/deleted")).unwrap();
+    repo.write("staged", "after\n");
+    repo.git(&["add", "staged"]);
+    repo.write("added", "new\n");
+    repo.git(&["add", "added"]);
+    repo.write("untracked/file", "?\n");
+    // stat data changes, content doesn't: only hashing with the repository's
+    // own object format tells that it is unmodified
+    repo.age("touched", 3600);
+    repo
+}
+
+#[test]
+fn object_hash_follows_the_repository() {
+    for (kind, format) in FORMATS {
+        let repo = Repo::init(&[format]);
+        assert_eq!(repo.open().object_hash(), kind, "{format}");
+    }
+}
+
+#[test]
+fn unborn_head_is_the_empty_tree_of_the_format() {
+    for (kind, format) in FORMATS {
+        let repo = Repo::init(&[format]);
+        let gix_repo = repo.open();
+        let tree = get_head_tree(&gix_repo).unwrap();
+        assert_eq!(tree.id, gix::ObjectId::empty_tree(kind), "{format}");
+    }
+}
+
+#[test]
+fn status_matches_git() {
+    for (_, format) in FORMATS {
+        let repo = changed_repo(format);
+        let statuses = repo
+            .builder()
+            .mode(IterMode::Both)
+            .untracked(UntrackedFilter::All)
+            .build()
+            .unwrap();
+        assert_eq!(
+            porcelain(statuses),
+            git_porcelain(&repo, &["-uall"]),
+            "{format}"
+        );
+    }
+}
+
+#[test]
+fn content_comparison_hashes_in_the_format() {
+    for (_, format) in FORMATS {
+        let repo = changed_repo(format);
+        let statuses = repo
+            .builder()
+            .mode(IterMode::Tracked)
+            .compare_content(true)
+            .build()
+            .unwrap();
+        let lines = porcelain(statuses);
+        assert!(
+            !lines.iter().any(|line| line.ends_with(" touched")),
+            "{format}: {lines:?}"
+        );
+        assert!(
+            lines.contains(&" M modified".to_owned()),
+            "{format}: {lines:?}"
+        );
+    }
+}
+
+#[test]
+fn parallel_status_matches_git() {
+    for (_, format) in FORMATS {
+        let repo = changed_repo(format);
+        let statuses = repo
+            .builder()
+            .mode(IterMode::Both)
+            .untracked(UntrackedFilter::All)
+            .parallel(2)
+            .build()
+            .unwrap();
+        assert_eq!(
+            porcelain(statuses),
+            git_porcelain(&repo, &["-uall"]),
+            "{format}"
+        );
+    }
+}
+
+#[test]
+fn base_revision_resolves_in_the_format() {
+    for (_, format) in FORMATS {
+        let repo = changed_repo(format);
+        repo.commit_all("second");
+        let statuses = repo
+            .builder()
+            .mode(IterMode::Tracked)
+            .base("HEAD~1")
+            .build()
+            .unwrap();
+        let lines = porcelain(statuses);
+        let expected = repo.git(&["diff", "--name-status", "HEAD~1", "--cached"]);
+        assert_eq!(lines.len(), expected.lines().count(), "{format}: {lines:?}");
+    }
+}
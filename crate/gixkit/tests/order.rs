@@ -0,0 +1,82 @@
+//! The order results come in, sequential and parallel
+
+mod common;
+
+use common::Repo;
+use gixkit::{IgnoredFilter, IterMode, RepoIterBuilder, UntrackedFilter};
+
+fn paths(builder: RepoIterBuilder) -> Vec<String> {
+    builder
+        .build()
+        .unwrap()
+        .map(|status| status.unwrap().path.to_string())
+        .collect()
+}
+
+/// Untracked and ignored paths spread over directories the walk visits in no
+/// particular order
+fn untracked_repo() -> Repo {
+    let repo = Repo::init(&[]);
+    repo.write(".gitignore", "*.log\n");
+    repo.write("b/tracked", "1\n");
+    repo.write("a/tracked", "1\n");
+    repo.commit_all("initial");
+    repo.write("b/tracked", "2\n");
+    for path in [
+        "z",
+        "a/new",
+        "a/deeper/new",
+        "b/new",
+        "c/new",
+        "a.log",
+        "b/x.log",
+    ] {
+        repo.write(path, "?\n");
+    }
+    repo
+}
+
+#[test]
+fn sequential_untracked_paths_are_sorted_ignored_last() {
+    let repo = untracked_repo();
+    let builder = || {
+        repo.builder()
+            .mode(IterMode::Both)
+            .untracked(UntrackedFilter::All)
+    };
+    let expected = [
+        "b/tracked",
+        "a/deeper/new",
+        "a/new",
+        "b/new",
+        "c/new",
+        "z",
+        "a.log",
+        "b/x.log",
+    ];
+    assert_eq!(
+        paths(builder().ignored(IgnoredFilter::Traditional)),
+        expected
+    );
+    assert_eq!(paths(builder()), expected[..6]);
+}
+
+#[test]
+fn ordered_parallel_output_is_the_sequential_order() {
+    let repo = untracked_repo();
+    for untracked in [UntrackedFilter::Normal, UntrackedFilter::All] {
+        for ignored in [IgnoredFilter::No, IgnoredFilter::Traditional] {
+            let builder = || {
+                repo.builder()
+                    .mode(IterMode::Both)
+                    .untracked(untracked)
+                    .ignored(ignored)
+            };
+            assert_eq!(
+                paths(builder().parallel(3)),
+                paths(builder()),
+                "{untracked:?} {ignored:?}"
+            );
+        }
+    }
+}
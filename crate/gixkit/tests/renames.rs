@@ -0,0 +1,101 @@
+//! Rename pairing, and what content it takes to pair
+
+mod common;
+
+use common::{git_porcelain, porcelain, Repo};
+use gixkit::IterMode;
+
+fn lines(count: usize) -> String {
+    (1..=count).map(|n| format!("line {n}\n")).collect()
+}
+
+/// A staged `git mv` of `big`, a staged rename with an edit of `edited`
+fn renamed_repo() -> Repo {
+    let repo = Repo::init(&[]);
+    repo.write("big", lines(300));
+    repo.write("edited", lines(200));
+    repo.commit_all("initial");
+    repo.git(&["mv", "big", "big-moved"]);
+    repo.git(&["mv", "edited", "edited-moved"]);
+    repo.write("edited-moved", lines(210));
+    repo.git(&["add", "edited-moved"]);
+    repo
+}
+
+#[test]
+fn staged_renames_match_git() {
+    let repo = renamed_repo();
+    let statuses = repo.builder().mode(IterMode::Tracked).build().unwrap();
+    assert_eq!(porcelain(statuses), git_porcelain(&repo, &["-uno"]));
+}
+
+/// Equal ids pair up without either blob being read, so no size limit keeps
+/// an exact rename apart; content over the limit is never scored, though
+#[test]
+fn exact_renames_pair_whatever_the_size_limit() {
+    let repo = renamed_repo();
+    // older than the index, so that stat data alone settles files over the limit
+    repo.age("big-moved", 3600);
+    repo.age("edited-moved", 3600);
+    repo.git(&["update-index", "--refresh"]);
+    let statuses = repo
+        .builder()
+        .mode(IterMode::Tracked)
+        .max_hash_size(100)
+        .build()
+        .unwrap();
+    assert_eq!(
+        porcelain(statuses),
+        ["A  edited-moved", "D  edited", "R  big -> big-moved"]
+    );
+}
+
+#[test]
+fn big_file_threshold_limits_inexact_renames() {
+    let repo = renamed_repo();
+    repo.git(&["config", "core.bigFileThreshold", "1k"]);
+    let statuses = repo.builder().mode(IterMode::Tracked).build().unwrap();
+    assert_eq!(
+        porcelain(statuses),
+        ["A  edited-moved", "D  edited", "R  big -> big-moved"]
+    );
+}
+
+#[test]
+fn worktree_renames_match_git() {
+    let repo = Repo::init(&[]);
+    repo.write("moved", lines(300));
+    repo.write("edited", lines(200));
+    repo.commit_all("initial");
+    std::fs::rename(repo.file("moved"), repo.file("moved-away")).unwrap();
+    std::fs::remove_file(repo.file("edited")).unwrap();
+    repo.write("edited-away", lines(210));
+    // git only pairs worktree renames of intent-to-add files
+    repo.git(&["add", "-N", "moved-away", "edited-away"]);
+
+    let statuses = repo.builder().build().unwrap();
+    assert_eq!(porcelain(statuses), git_porcelain(&repo, &[]));
+}
+
+/// Clean filter output is hashed as it comes, like any other file content
+#[test]
+fn clean_filter_output_is_hashed() {
+    let repo = Repo::init(&[]);
+    repo.git(&["config", "filter.upper.clean", "tr a-z A-Z"]);
+    repo.write(".gitattributes", "*.txt filter=upper\n");
+    repo.write("clean.txt", "same\n");
+    repo.write("changed.txt", "before\n");
+    repo.commit_all("initial");
+    repo.write("changed.txt", "after\n");
+    repo.age("clean.txt", 3600);
+    repo.age("changed.txt", 3600);
+
+    let statuses = repo
+        .builder()
+        .mode(IterMode::Tracked)
+        .compare_content(true)
+        .build()
+        .unwrap();
+    assert_eq!(porcelain(statuses), [" M changed.txt"]);
+    assert_eq!(git_porcelain(&repo, &["-uno"]), [" M changed.txt"]);
+}
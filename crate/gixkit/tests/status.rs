@@ -0,0 +1,663 @@
+//! Status against git's, for the cases that are easy to get wrong
+
+mod common;
+
+use common::{git_porcelain, porcelain, Repo};
+use gixkit::{IgnoredFilter, IterMode, UntrackedFilter};
+use std::time::{Duration, SystemTime};
+
+/// git zeroes the size of an entry whose file changed without its stat data
+/// showing it; emptying the file afterwards mustn't make it look clean
+#[test]
+fn racily_smudged_entry_emptied_is_modified() {
+    let repo = Repo::init(&[]);
+    repo.git(&["config", "core.trustCTime", "false"]);
+    let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(2_000_000_000);
+    repo.write("other", "x\n");
+    repo.git(&["add", "other"]);
+    repo.write("file", "content\n");
+    repo.set_mtime("file", mtime);
+    repo.git(&["add", "file"]);
+    // same size, same mtime: git smudges the entry on its next index write
+    repo.write("file", "CONTENT\n");
+    repo.set_mtime("file", mtime);
+    repo.write("other", "y\n");
+    repo.git(&["add", "other"]);
+    assert!(repo
+        .git(&["ls-files", "--debug", "file"])
+        .contains("size: 0\t"));
+
+    repo.write("file", "");
+    repo.set_mtime("file", mtime);
+    // a newer index, so the entry is no longer racy by it
+    repo.set_mtime(".git/index", mtime + Duration::from_secs(100));
+
+    let statuses = repo.builder().mode(IterMode::Tracked).build().unwrap();
+    assert_eq!(porcelain(statuses), git_porcelain(&repo, &["-uno"]));
+}
+
+/// A file whose content can't be had is no more known to be clean than git
+/// takes it to be
+#[test]
+fn failing_clean_filter_is_modified() {
+    let repo = Repo::init(&[]);
+    repo.write("file", "content\n");
+    repo.write(".gitattributes", "file filter=broken\n");
+    repo.commit_all("initial");
+    repo.git(&["config", "filter.broken.clean", "false"]);
+    repo.git(&["config", "filter.broken.required", "true"]);
+    repo.age("file", 3600);
+
+    let statuses = repo.builder().mode(IterMode::Tracked).build().unwrap();
+    assert_eq!(porcelain(statuses), [" M file"]);
+}
+
+/// Untracked and ignored paths fill both columns, as in `??` and `!!`
+#[test]
+fn untracked_and_ignored_fill_both_columns() {
+    let repo = Repo::init(&[]);
+    repo.write(".gitignore", "build/\n");
+    repo.commit_all("initial");
+    repo.write("build/out", "o\n");
+    repo.write("new", "n\n");
+
+    let statuses = repo
+        .builder()
+        .ignored(IgnoredFilter::Traditional)
+        .build()
+        .unwrap();
+    let lines = porcelain(statuses);
+    assert_eq!(lines, ["!! build/", "?? new"]);
+    assert_eq!(lines, git_porcelain(&repo, &["--ignored"]));
+}
+
+fn pathspec_repo() -> Repo {
+    let repo = Repo::init(&[]);
+    for path in ["a.txt", "b.rs", "sub/c.txt", "sub/e.rs", "sub/deep/f.txt"] {
+        repo.write(path, "1\n");
+    }
+    repo.commit_all("initial");
+    for path in ["a.txt", "b.rs", "sub/c.txt", "sub/deep/f.txt"] {
+        repo.write(path, "2\n");
+    }
+    for path in [
+        "new.txt",
+        "sub/G.TXT",
+        "sub/new.rs",
+        "sub/new.txt",
+        "other/h.txt",
+    ] {
+        repo.write(path, "?\n");
+    }
+    repo
+}
+
+/// Each kind of pathspec narrows the status as it narrows `git status --
+/// <pathspec>`
+#[test]
+fn pathspecs_narrow_as_in_git() {
+    let repo = pathspec_repo();
+    let specs: &[&[&str]] = &[
+        &["a.txt"],
+        &["sub"],
+        &["sub/c.txt", "other"],
+        &["*.txt"],
+        &["sub/*.rs"],
+        &[":(exclude)*.txt"],
+        &["sub", ":(exclude)sub/deep"],
+        &[":!*.rs"],
+        &[":(icase)sub/g.txt"],
+        &[":(icase)SUB/*.txt"],
+        &[":(top)sub/deep"],
+        &[":/b.rs"],
+    ];
+    for spec in specs {
+        let statuses = repo.builder().pathspec(spec.iter()).build().unwrap();
+        assert_eq!(
+            porcelain(statuses),
+            git_porcelain(&repo, &[&["--"], *spec].concat()),
+            "pathspec {spec:?}"
+        );
+    }
+}
+
+/// Pathspecs are relative to the directory the repository was opened from,
+/// unless `:(top)` says otherwise
+#[test]
+fn pathspecs_are_relative_to_the_current_directory() {
+    let repo = pathspec_repo();
+    let cwd = std::env::current_dir().unwrap();
+    std::env::set_current_dir(repo.file("sub")).unwrap();
+    // the directory is taken once, when the repository is opened
+    let builder = repo.builder();
+    std::env::set_current_dir(cwd).unwrap();
+
+    let spec = ["*.txt", ":(top)b.rs"];
+    let statuses = builder.pathspec(spec).build().unwrap();
+    // porcelain paths are from the top whichever directory git runs in
+    let mut lines: Vec<String> = repo
+        .git(&[&["-C", "sub", "status", "--porcelain", "--"], &spec[..]].concat())
+        .lines()
+        .map(str::to_owned)
+        .collect();
+    lines.sort();
+    assert_eq!(
+        lines,
+        [
+            " M b.rs",
+            " M sub/c.txt",
+            " M sub/deep/f.txt",
+            "?? sub/new.txt"
+        ]
+    );
+    assert_eq!(porcelain(statuses), lines);
+}
+
+/// Each combination of stages in the index has git's two-letter code for it
+#[test]
+fn conflicts_have_gits_codes() {
+    let repo = Repo::init(&[]);
+    repo.write("file", "1\n");
+    repo.commit_all("initial");
+    repo.write("blob", "conflicted\n");
+    let blob = repo.git(&["hash-object", "-w", "blob"]);
+    std::fs::remove_file(repo.file("blob")).unwrap();
+
+    // base, ours and theirs are stages 1, 2 and 3
+    let conflicts = [
+        ("uu", &[1, 2, 3][..]),
+        ("aa", &[2, 3]),
+        ("du", &[1, 3]),
+        ("ud", &[1, 2]),
+        ("au", &[2]),
+        ("ua", &[3]),
+        ("dd", &[1]),
+    ];
+    let mut index_info = String::new();
+    for (path, stages) in conflicts {
+        for stage in stages {
+            index_info += &format!("100644 {} {stage}\t{path}\n", blob.trim());
+        }
+        repo.write(path, "<<<<<<<\n");
+    }
+    repo.git_with_input(&["update-index", "--index-info"], &index_info);
+
+    let statuses = repo.builder().build().unwrap();
+    let lines = porcelain(statuses);
+    assert_eq!(
+        lines,
+        ["AA aa", "AU au", "DD dd", "DU du", "UA ua", "UD ud", "UU uu"]
+    );
+    assert_eq!(lines, git_porcelain(&repo, &[]));
+}
+
+/// A mode change, a file turned symlink and a gitlink, staged or not
+#[test]
+fn mode_and_type_changes_and_gitlinks() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let repo = Repo::init(&[]);
+    for path in ["exec", "exec-staged", "link", "link-staged"] {
+        repo.write(path, "x\n");
+    }
+    repo.commit_all("initial");
+    for path in ["exec", "exec-staged"] {
+        std::fs::set_permissions(repo.file(path), std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    for path in ["link", "link-staged"] {
+        std::fs::remove_file(repo.file(path)).unwrap();
+        std::os::unix::fs::symlink("exec", repo.file(path)).unwrap();
+    }
+    repo.git(&["add", "exec-staged", "link-staged"]);
+    // gitlinks to a commit with nothing checked out: one without even its
+    // directory
+    let head = repo.git(&["rev-parse", "HEAD"]);
+    for path in ["sub", "sub-missing"] {
+        let cacheinfo = format!("160000,{},{path}", head.trim());
+        repo.git(&["update-index", "--add", "--cacheinfo", &cacheinfo]);
+    }
+    std::fs::create_dir(repo.file("sub")).unwrap();
+
+    let statuses = repo.builder().build().unwrap();
+    let lines = porcelain(statuses);
+    assert_eq!(
+        lines,
+        [
+            " M exec",
+            " T link",
+            "A  sub",
+            "AD sub-missing",
+            "M  exec-staged",
+            "T  link-staged"
+        ]
+    );
+    assert_eq!(lines, git_porcelain(&repo, &[]));
+}
+
+/// Checked-out submodules carry what git's porcelain v2 `S<c><m><u>` field
+/// says about them
+#[test]
+fn submodule_states_are_gits() {
+    let upstream = Repo::init(&[]);
+    upstream.write("file", "1\n");
+    upstream.commit_all("initial");
+    let repo = Repo::init(&[]);
+    repo.write("file", "1\n");
+    let url = upstream.path().to_str().unwrap();
+    for path in ["clean", "commits", "modified", "untracked", "everything"] {
+        repo.git(&[
+            "-c",
+            "protocol.file.allow=always",
+            "submodule",
+            "add",
+            "-q",
+            url,
+            path,
+        ]);
+    }
+    repo.commit_all("submodules");
+    for path in ["commits", "everything"] {
+        repo.write(&format!("{path}/file"), "2\n");
+        repo.git(&["-C", path, "commit", "-q", "-am", "new commit"]);
+    }
+    for path in ["modified", "everything"] {
+        repo.write(&format!("{path}/file"), "3\n");
+    }
+    for path in ["untracked", "everything"] {
+        repo.write(&format!("{path}/new"), "?\n");
+    }
+
+    let statuses: Vec<_> = repo
+        .builder()
+        .build()
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let mut states: Vec<String> = statuses
+        .iter()
+        .map(|status| {
+            let state = status.submodule.expect("a checked-out submodule");
+            let flag = |set, c| if set { c } else { '.' };
+            format!(
+                "S{}{}{} {}",
+                flag(state.new_commits, 'C'),
+                flag(state.modified_content, 'M'),
+                flag(state.untracked_content, 'U'),
+                status.path
+            )
+        })
+        .collect();
+    states.sort();
+    assert_eq!(
+        states,
+        [
+            "S..U untracked",
+            "S.M. modified",
+            "SC.. commits",
+            "SCMU everything"
+        ]
+    );
+    // `1 XY <sub> <mH> <mI> <mW> <hH> <hI> <path>`
+    let mut git_states: Vec<String> = repo
+        .git(&["status", "--porcelain=v2"])
+        .lines()
+        .map(|line| {
+            let fields: Vec<&str> = line.split(' ').collect();
+            format!("{} {}", fields[2], fields[8])
+        })
+        .collect();
+    git_states.sort();
+    assert_eq!(states, git_states);
+    assert_eq!(
+        porcelain(statuses.into_iter().map(Ok)),
+        git_porcelain(&repo, &[])
+    );
+}
+
+/// Dotfiles are untracked like any other file, and ignored only by the rules;
+/// `.git` alone is passed over
+#[test]
+fn dotfiles_are_untracked_like_any_file() {
+    let repo = Repo::init(&[]);
+    repo.write(".gitignore", ".env\n");
+    repo.commit_all("initial");
+    repo.write(".env", "SECRET=1\n");
+    repo.write(".env.example", "SECRET=\n");
+    repo.write(".editorconfig", "root = true\n");
+    repo.write(".github/workflows/ci.yml", "on: push\n");
+
+    for untracked in [UntrackedFilter::Normal, UntrackedFilter::All] {
+        let statuses = repo
+            .builder()
+            .untracked(untracked)
+            .ignored(IgnoredFilter::Traditional)
+            .build()
+            .unwrap();
+        let flag = match untracked {
+            UntrackedFilter::All => "-uall",
+            _ => "-unormal",
+        };
+        assert_eq!(
+            porcelain(statuses),
+            git_porcelain(&repo, &["--ignored", flag]),
+            "{flag}"
+        );
+    }
+    let statuses = repo.builder().build().unwrap();
+    assert_eq!(
+        porcelain(statuses),
+        ["?? .editorconfig", "?? .env.example", "?? .github/"]
+    );
+}
+
+/// `-unormal` collapses a directory with nothing tracked in it into `dir/`,
+/// `-uall` lists its files
+#[test]
+fn untracked_directories_collapse_only_with_normal() {
+    let repo = Repo::init(&[]);
+    repo.write("mixed/tracked", "t\n");
+    repo.commit_all("initial");
+    repo.write("mixed/untracked", "u\n");
+    repo.write("new/a", "a\n");
+    repo.write("new/deeper/b", "b\n");
+
+    let statuses = repo
+        .builder()
+        .untracked(UntrackedFilter::Normal)
+        .build()
+        .unwrap();
+    let lines = porcelain(statuses);
+    assert_eq!(lines, ["?? mixed/untracked", "?? new/"]);
+    assert_eq!(lines, git_porcelain(&repo, &["-unormal"]));
+
+    let statuses = repo
+        .builder()
+        .untracked(UntrackedFilter::All)
+        .build()
+        .unwrap();
+    let lines = porcelain(statuses);
+    assert_eq!(lines, ["?? mixed/untracked", "?? new/a", "?? new/deeper/b"]);
+    assert_eq!(lines, git_porcelain(&repo, &["-uall"]));
+}
+
+/// A collapsed `dir/` has the oldest modification time of its files, the
+/// newest, and their total size
+#[test]
+fn collapsed_directory_metadata_spans_its_files() {
+    let repo = Repo::init(&[]);
+    repo.write("tracked", "t\n");
+    repo.commit_all("initial");
+    let oldest = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+    let newest = oldest + Duration::from_secs(3600);
+    repo.write("new/a", "a\n");
+    repo.write("new/deeper/b", "bbbb\n");
+    repo.write("new/deeper/c", "");
+    repo.set_mtime("new/a", newest);
+    repo.set_mtime("new/deeper/b", oldest);
+    repo.set_mtime("new/deeper/c", oldest + Duration::from_secs(60));
+
+    let statuses: Vec<_> = repo
+        .builder()
+        .untracked(UntrackedFilter::Normal)
+        .include_metadata(true)
+        .build()
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(statuses.len(), 1);
+    assert_eq!(statuses[0].path, "new/");
+    let metadata = statuses[0].metadata.as_ref().expect("metadata");
+    assert_eq!(metadata.modified_time, oldest);
+    assert_eq!(metadata.newest_modified_time, Some(newest));
+    assert_eq!(metadata.size, 7);
+
+    // a file on its own has no span
+    let statuses: Vec<_> = repo
+        .builder()
+        .untracked(UntrackedFilter::All)
+        .include_metadata(true)
+        .build()
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let a = statuses
+        .iter()
+        .find(|status| status.path == "new/a")
+        .unwrap();
+    let metadata = a.metadata.as_ref().expect("metadata");
+    assert_eq!((metadata.modified_time, metadata.size), (newest, 2));
+    assert_eq!(metadata.newest_modified_time, None);
+}
+
+/// A repository inside the worktree is one untracked `dir/`, even when every
+/// untracked file is listed
+#[test]
+fn nested_repository_is_one_untracked_entry() {
+    let repo = Repo::init(&[]);
+    repo.write("file", "1\n");
+    repo.commit_all("initial");
+    repo.git(&["init", "-q", "vendor/lib"]);
+    repo.write("vendor/lib/file", "1\n");
+    repo.git(&["-C", "vendor/lib", "add", "file"]);
+    repo.git(&["-C", "vendor/lib", "commit", "-q", "-m", "nested"]);
+    repo.write("vendor/lib/file", "2\n");
+    repo.write("vendor/lib/new", "?\n");
+
+    // with `-unormal` the directory holding it collapses as usual
+    for (untracked, flag, entry) in [
+        (UntrackedFilter::Normal, "-unormal", "?? vendor/"),
+        (UntrackedFilter::All, "-uall", "?? vendor/lib/"),
+    ] {
+        let statuses = repo.builder().untracked(untracked).build().unwrap();
+        let lines = porcelain(statuses);
+        assert_eq!(lines, [entry], "{flag}");
+        assert_eq!(lines, git_porcelain(&repo, &[flag]), "{flag}");
+    }
+}
+
+/// Recursing into a nested repository adds its own status, its paths prefixed
+/// with the directory it sits in
+#[test]
+fn nested_repository_status_is_prefixed() {
+    let repo = Repo::init(&[]);
+    repo.write("file", "1\n");
+    repo.commit_all("initial");
+    repo.git(&["init", "-q", "vendor/lib"]);
+    repo.write("vendor/lib/file", "1\n");
+    repo.git(&["-C", "vendor/lib", "add", "file"]);
+    repo.git(&["-C", "vendor/lib", "commit", "-q", "-m", "nested"]);
+    repo.write("vendor/lib/file", "2\n");
+    repo.write("vendor/lib/staged", "s\n");
+    repo.git(&["-C", "vendor/lib", "add", "staged"]);
+    repo.write("vendor/lib/new", "?\n");
+
+    let statuses = repo
+        .builder()
+        .untracked(UntrackedFilter::All)
+        .recurse_nested_repos(true)
+        .build()
+        .unwrap();
+    let lines = porcelain(statuses);
+    assert_eq!(
+        lines,
+        [
+            " M vendor/lib/file",
+            "?? vendor/lib/",
+            "?? vendor/lib/new",
+            "A  vendor/lib/staged"
+        ]
+    );
+    let mut expected = git_porcelain(&repo, &["-uall"]);
+    expected.extend(
+        repo.git(&["-C", "vendor/lib", "status", "--porcelain", "-uall"])
+            .lines()
+            .map(|line| format!("{}vendor/lib/{}", &line[..3], &line[3..])),
+    );
+    expected.sort();
+    assert_eq!(lines, expected);
+}
+
+/// `git add -N` is a worktree addition, ` A`
+#[test]
+fn intent_to_add_is_added_in_the_worktree() {
+    let repo = Repo::init(&[]);
+    repo.write("file", "1\n");
+    repo.commit_all("initial");
+    repo.write("new", "n\n");
+    repo.git(&["add", "-N", "new"]);
+
+    let statuses = repo.builder().build().unwrap();
+    let lines = porcelain(statuses);
+    assert_eq!(lines, [" A new"]);
+    assert_eq!(lines, git_porcelain(&repo, &[]));
+}
+
+/// A skip-worktree entry is clean however its file looks, gone or changed
+#[test]
+fn skip_worktree_entries_are_hidden() {
+    let repo = Repo::init(&[]);
+    for path in ["gone", "changed", "plain"] {
+        repo.write(path, "1\n");
+    }
+    repo.commit_all("initial");
+    repo.git(&["update-index", "--skip-worktree", "gone", "changed"]);
+    std::fs::remove_file(repo.file("gone")).unwrap();
+    repo.write("changed", "2\n");
+    repo.write("plain", "2\n");
+
+    let statuses = repo.builder().build().unwrap();
+    let lines = porcelain(statuses);
+    assert_eq!(lines, [" M plain"]);
+    assert_eq!(lines, git_porcelain(&repo, &[]));
+}
+
+/// Outside a sparse-checkout cone nothing is missing, and a sparse index's
+/// directory entries stand for the files under them
+#[test]
+fn sparse_checkout_cone() {
+    let repo = Repo::init(&[]);
+    for path in [
+        "top",
+        "in/file",
+        "in/deep/file",
+        "out/file",
+        "out/deep/file",
+    ] {
+        repo.write(path, "1\n");
+    }
+    repo.commit_all("initial");
+    for sparse_index in ["--no-sparse-index", "--sparse-index"] {
+        repo.git(&["sparse-checkout", "init", "--cone", sparse_index]);
+        repo.git(&["sparse-checkout", "set", "in"]);
+        assert!(!repo.file("out").exists());
+        let sparse_entries = repo.git(&["ls-files", "--sparse"]);
+        assert_eq!(
+            sparse_entries.lines().any(|line| line == "out/"),
+            sparse_index == "--sparse-index"
+        );
+        repo.write("in/file", "2\n");
+        repo.write("top", "2\n");
+        repo.write("in/new", "?\n");
+
+        let statuses = repo.builder().build().unwrap();
+        let lines = porcelain(statuses);
+        assert_eq!(
+            lines,
+            [" M in/file", " M top", "?? in/new"],
+            "{sparse_index}"
+        );
+        assert_eq!(lines, git_porcelain(&repo, &[]), "{sparse_index}");
+
+        repo.git(&["sparse-checkout", "disable"]);
+        repo.git(&["checkout", "--", "."]);
+        std::fs::remove_file(repo.file("in/new")).unwrap();
+    }
+}
+
+/// Every source of ignore rules applies, later and deeper ones overriding
+/// earlier ones, negations included
+#[test]
+fn untracked_walk_honors_every_ignore_source() {
+    let repo = Repo::init(&[]);
+    let excludes = tempfile::NamedTempFile::new().unwrap();
+    std::fs::write(excludes.path(), "*.swp\nglobal/\n").unwrap();
+    repo.git(&[
+        "config",
+        "core.excludesFile",
+        excludes.path().to_str().unwrap(),
+    ]);
+    repo.write(".git/info/exclude", "*.local\n!keep.local\n");
+    repo.write(".gitignore", "*.log\ntarget/\n");
+    repo.write("sub/.gitignore", "!important.log\n*.tmp\n");
+    repo.commit_all("initial");
+    for path in [
+        "a.swp",
+        "global/file",
+        "config.local",
+        "keep.local",
+        "debug.log",
+        "target/out",
+        "sub/important.log",
+        "sub/other.log",
+        "sub/x.tmp",
+        "x.tmp",
+        "new",
+    ] {
+        repo.write(path, "?\n");
+    }
+
+    let statuses = repo
+        .builder()
+        .untracked(UntrackedFilter::All)
+        .build()
+        .unwrap();
+    let lines = porcelain(statuses);
+    assert_eq!(
+        lines,
+        [
+            "?? keep.local",
+            "?? new",
+            "?? sub/important.log",
+            "?? x.tmp"
+        ]
+    );
+    assert_eq!(lines, git_porcelain(&repo, &["-uall"]));
+
+    let statuses = repo
+        .builder()
+        .ignored(IgnoredFilter::Traditional)
+        .build()
+        .unwrap();
+    assert_eq!(porcelain(statuses), git_porcelain(&repo, &["--ignored"]));
+}
+
+/// Paths in HEAD but not in the index are staged deletions, `D `, whether the
+/// file is gone or still there untracked
+#[test]
+fn staged_deletions_are_reported() {
+    let repo = Repo::init(&[]);
+    for path in ["removed", "cached", "dir/a", "dir/b", "kept"] {
+        repo.write(path, "1\n");
+    }
+    repo.commit_all("initial");
+    repo.git(&["rm", "-q", "removed"]);
+    repo.git(&["rm", "-q", "--cached", "cached"]);
+    repo.git(&["rm", "-q", "-r", "dir"]);
+
+    let statuses = repo.builder().build().unwrap();
+    let lines = porcelain(statuses);
+    assert_eq!(
+        lines,
+        [
+            "?? cached",
+            "D  cached",
+            "D  dir/a",
+            "D  dir/b",
+            "D  removed"
+        ]
+    );
+    assert_eq!(lines, git_porcelain(&repo, &[]));
+
+    let statuses = repo.builder().mode(IterMode::Tracked).build().unwrap();
+    assert_eq!(porcelain(statuses), git_porcelain(&repo, &["-uno"]));
+}
@@ -0,0 +1,590 @@
+//! The index's untracked cache extension (`UNTR`), in git's own layout, and
+//! writing it back into the index file
+
+use anyhow::{Context, Result};
+use gix::bstr::{BString, ByteSlice};
+use gix::index::entry::{stat, Stat};
+use gix_hash::ObjectId;
+use std::io::Write;
+use std::path::Path;
+
+const SIGNATURE: &[u8; 4] = b"UNTR";
+const END_OF_INDEX_ENTRY: &[u8; 4] = b"EOIE";
+
+/// `dir_flags` for `-unormal`: untracked directories show as `dir/`, unless
+/// they hold no untracked file
+pub const DIR_SHOW_OTHER_DIRECTORIES: u32 = 1 << 1;
+pub const DIR_HIDE_EMPTY_DIRECTORIES: u32 = 1 << 2;
+
+/// Stat data and blob id of an exclude file; no id when there is no such file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OidStat {
+    pub stat: Stat,
+    pub id: Option<ObjectId>,
+}
+
+/// What `git status` remembers about untracked files, one node per directory
+#[derive(Debug, Clone)]
+pub struct UntrackedCache {
+    /// `Location <worktree>, system <kernel>`, so a copied repository starts over
+    pub ident: BString,
+    pub info_exclude: OidStat,
+    pub excludes_file: OidStat,
+    /// Which kind of walk the entries are for
+    pub dir_flags: u32,
+    /// Name of the per-directory ignore file, `.gitignore`
+    pub exclude_per_dir: BString,
+    /// `dirs[0]` is the worktree root, when there is one
+    pub dirs: Vec<CachedDir>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CachedDir {
+    /// Name within the parent, empty for the root
+    pub name: BString,
+    /// Untracked files directly inside, and untracked directories as `dir/`
+    pub untracked: Vec<BString>,
+    /// Subdirectories the walk went into, as positions in `dirs`
+    pub children: Vec<usize>,
+    /// The directory's own stat data when it was read
+    pub stat: Stat,
+    /// `untracked` is complete as of `stat`
+    pub valid: bool,
+    /// Only read far enough to tell whether it holds any untracked file
+    pub check_only: bool,
+    /// Blob id of the directory's `.gitignore`
+    pub exclude_oid: Option<ObjectId>,
+    /// Visited by the current walk; nodes left unvisited aren't written back
+    pub recurse: bool,
+}
+
+impl UntrackedCache {
+    /// The untracked cache stored in the index file `data`, if there is one
+    /// that parses
+    pub fn from_index(data: &[u8], hash_kind: gix_hash::Kind) -> Option<Self> {
+        let layout = IndexLayout::parse(data, hash_kind)?;
+        let (_, range) = layout
+            .extensions
+            .iter()
+            .find(|(signature, _)| signature == SIGNATURE)?;
+        decode(&data[range.clone()], hash_kind.len_in_bytes())
+    }
+
+    /// The extension's payload, as git lays it out
+    pub fn encode(&self, hash_len: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        push_varint(&mut out, self.ident.len() as u64);
+        out.extend_from_slice(&self.ident);
+        push_stat(&mut out, &self.info_exclude.stat);
+        push_stat(&mut out, &self.excludes_file.stat);
+        out.extend_from_slice(&self.dir_flags.to_be_bytes());
+        push_oid(&mut out, self.info_exclude.id, hash_len);
+        push_oid(&mut out, self.excludes_file.id, hash_len);
+        out.extend_from_slice(&self.exclude_per_dir);
+        out.push(0);
+
+        let order = if self.dirs.is_empty() {
+            Vec::new()
+        } else {
+            let mut order = Vec::new();
+            self.visit_order(0, &mut order);
+            order
+        };
+        push_varint(&mut out, order.len() as u64);
+        if order.is_empty() {
+            return out;
+        }
+        for &i in &order {
+            let dir = &self.dirs[i];
+            let untracked: &[BString] = if dir.valid { &dir.untracked } else { &[] };
+            push_varint(&mut out, untracked.len() as u64);
+            let children = dir.children.iter().filter(|&&c| self.dirs[c].recurse);
+            push_varint(&mut out, children.count() as u64);
+            out.extend_from_slice(&dir.name);
+            out.push(0);
+            for name in untracked {
+                out.extend_from_slice(name);
+                out.push(0);
+            }
+        }
+        let dirs = || order.iter().map(|&i| &self.dirs[i]);
+        push_ewah(&mut out, dirs().map(|dir| dir.valid));
+        push_ewah(&mut out, dirs().map(|dir| dir.check_only));
+        push_ewah(&mut out, dirs().map(|dir| dir.exclude_oid.is_some()));
+        for dir in dirs().filter(|dir| dir.valid) {
+            push_stat(&mut out, &dir.stat);
+        }
+        for id in dirs().filter_map(|dir| dir.exclude_oid) {
+            out.extend_from_slice(id.as_bytes());
+        }
+        out.push(0);
+        out
+    }
+
+    /// Depth first from `index`, through the directories visited this time
+    fn visit_order(&self, index: usize, order: &mut Vec<usize>) {
+        order.push(index);
+        for &child in &self.dirs[index].children {
+            if self.dirs[child].recurse {
+                self.visit_order(child, order);
+            }
+        }
+    }
+}
+
+fn decode(data: &[u8], hash_len: usize) -> Option<UntrackedCache> {
+    let mut reader = Reader(data.strip_suffix(b"\0")?);
+    let ident_len = reader.varint()? as usize;
+    let ident = reader.take(ident_len)?.into();
+    // both stats and the flags come first, then both ids
+    let info_exclude_stat = reader.stat()?;
+    let excludes_file_stat = reader.stat()?;
+    let dir_flags = reader.u32()?;
+    let info_exclude = OidStat {
+        stat: info_exclude_stat,
+        id: reader.oid(hash_len)?,
+    };
+    let excludes_file = OidStat {
+        stat: excludes_file_stat,
+        id: reader.oid(hash_len)?,
+    };
+    let exclude_per_dir = reader.until_nul()?.into();
+    let mut cache = UntrackedCache {
+        ident,
+        info_exclude,
+        excludes_file,
+        dir_flags,
+        exclude_per_dir,
+        dirs: Vec::new(),
+    };
+
+    let count = reader.varint()? as usize;
+    if count == 0 {
+        return reader.0.is_empty().then_some(cache);
+    }
+    read_dir(&mut reader, &mut cache.dirs)?;
+    if cache.dirs.len() != count {
+        return None;
+    }
+    let mut bitmap = || -> Option<Vec<usize>> {
+        let (bits, rest) = gix_bitmap::ewah::decode(reader.0).ok()?;
+        reader.0 = rest;
+        let mut set = Vec::new();
+        bits.for_each_set_bit(|i| {
+            set.push(i);
+            Some(())
+        })?;
+        set.iter().all(|&i| i < count).then_some(set)
+    };
+    let valid = bitmap()?;
+    let check_only = bitmap()?;
+    let hash_valid = bitmap()?;
+    for i in check_only {
+        cache.dirs[i].check_only = true;
+    }
+    for i in valid {
+        cache.dirs[i].stat = reader.stat()?;
+        cache.dirs[i].valid = true;
+    }
+    for i in hash_valid {
+        cache.dirs[i].exclude_oid = reader.oid(hash_len)?;
+    }
+    for dir in &mut cache.dirs {
+        // whatever was written was visited by the walk that wrote it
+        dir.recurse = true;
+        if !dir.valid {
+            dir.untracked.clear();
+        }
+    }
+    reader.0.is_empty().then_some(cache)
+}
+
+fn read_dir(reader: &mut Reader<'_>, dirs: &mut Vec<CachedDir>) -> Option<usize> {
+    let untracked_count = reader.varint()?;
+    let children_count = reader.varint()?;
+    let name = reader.until_nul()?.into();
+    let untracked = (0..untracked_count)
+        .map(|_| reader.until_nul().map(BString::from))
+        .collect::<Option<_>>()?;
+    let index = dirs.len();
+    dirs.push(CachedDir {
+        name,
+        untracked,
+        ..Default::default()
+    });
+    for _ in 0..children_count {
+        let child = read_dir(reader, dirs)?;
+        dirs[index].children.push(child);
+    }
+    Some(index)
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn until_nul(&mut self) -> Option<&'a [u8]> {
+        let end = self.0.find_byte(0)?;
+        let name = self.take(end)?;
+        self.take(1)?;
+        Some(name)
+    }
+
+    /// git's varint, where each continuation also adds one
+    fn varint(&mut self) -> Option<u64> {
+        let mut byte = self.take(1)?[0];
+        let mut value = u64::from(byte & 0x7f);
+        while byte & 0x80 != 0 {
+            byte = self.take(1)?[0];
+            value = ((value + 1).checked_mul(1 << 7))? + u64::from(byte & 0x7f);
+        }
+        Some(value)
+    }
+
+    fn stat(&mut self) -> Option<Stat> {
+        let mut n = || self.u32();
+        Some(Stat {
+            ctime: stat::Time {
+                secs: n()?,
+                nsecs: n()?,
+            },
+            mtime: stat::Time {
+                secs: n()?,
+                nsecs: n()?,
+            },
+            dev: n()?,
+            ino: n()?,
+            uid: n()?,
+            gid: n()?,
+            size: n()?,
+        })
+    }
+
+    /// `None` inside for the null id
+    fn oid(&mut self, hash_len: usize) -> Option<Option<ObjectId>> {
+        let id = ObjectId::try_from(self.take(hash_len)?).ok()?;
+        Some((!id.is_null()).then_some(id))
+    }
+}
+
+fn push_varint(out: &mut Vec<u8>, mut value: u64) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value != 0 {
+        value -= 1;
+        bytes.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+/// On-disk stat data: ctime, mtime, dev, ino, uid, gid, size
+fn push_stat(out: &mut Vec<u8>, stat: &Stat) {
+    for n in [
+        stat.ctime.secs,
+        stat.ctime.nsecs,
+        stat.mtime.secs,
+        stat.mtime.nsecs,
+        stat.dev,
+        stat.ino,
+        stat.uid,
+        stat.gid,
+        stat.size,
+    ] {
+        out.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+fn push_oid(out: &mut Vec<u8>, id: Option<ObjectId>, hash_len: usize) {
+    match id {
+        Some(id) => out.extend_from_slice(id.as_bytes()),
+        None => out.extend(std::iter::repeat_n(0, hash_len)),
+    }
+}
+
+/// An EWAH bitmap, built up bit by bit the way git's `ewah_set` builds it, so
+/// that it comes out as git would write it: a word of ones turns into a run
+fn push_ewah(out: &mut Vec<u8>, bits: impl Iterator<Item = bool>) {
+    let mut ewah = Ewah {
+        words: vec![0],
+        marker: 0,
+        bit_size: 0,
+    };
+    for (i, bit) in bits.enumerate() {
+        if bit {
+            ewah.set(i);
+        }
+    }
+    out.extend_from_slice(&(ewah.bit_size as u32).to_be_bytes());
+    out.extend_from_slice(&(ewah.words.len() as u32).to_be_bytes());
+    for word in &ewah.words {
+        out.extend_from_slice(&word.to_be_bytes());
+    }
+    out.extend_from_slice(&(ewah.marker as u32).to_be_bytes());
+}
+
+/// An EWAH bitmap being built: marker words, each a run bit, a run length and
+/// a count of the literal words that follow it
+struct Ewah {
+    words: Vec<u64>,
+    /// Position of the last marker word
+    marker: usize,
+    bit_size: usize,
+}
+
+impl Ewah {
+    const RUN_LEN_MASK: u64 = (1 << 32) - 1;
+
+    /// Set bit `i`, past every bit set so far
+    fn set(&mut self, i: usize) {
+        let bit = 1 << (i % 64);
+        let dist = (i + 1).div_ceil(64) - self.bit_size.div_ceil(64);
+        self.bit_size = i + 1;
+        if dist > 0 {
+            if dist > 1 {
+                self.add_empty_words(false, dist as u64 - 1);
+            }
+            self.add_literal(bit);
+            return;
+        }
+        if self.literal_words() == 0 {
+            self.set_running_len(self.running_len() - 1);
+            self.add_literal(bit);
+            return;
+        }
+        let last = self.words.last_mut().expect("a literal word");
+        *last |= bit;
+        if *last == u64::MAX {
+            self.words.pop();
+            self.set_literal_words(self.literal_words() - 1);
+            self.add_empty_word(true);
+        }
+    }
+
+    fn run_bit(&self) -> bool {
+        self.words[self.marker] & 1 != 0
+    }
+
+    fn running_len(&self) -> u64 {
+        (self.words[self.marker] >> 1) & Self::RUN_LEN_MASK
+    }
+
+    fn literal_words(&self) -> u64 {
+        self.words[self.marker] >> 33
+    }
+
+    fn set_run_bit(&mut self, bit: bool) {
+        self.words[self.marker] = (self.words[self.marker] & !1) | u64::from(bit);
+    }
+
+    fn set_running_len(&mut self, len: u64) {
+        let word = &mut self.words[self.marker];
+        *word = (*word & !(Self::RUN_LEN_MASK << 1)) | (len << 1);
+    }
+
+    fn set_literal_words(&mut self, count: u64) {
+        let word = &mut self.words[self.marker];
+        *word = (*word & ((1 << 33) - 1)) | (count << 33);
+    }
+
+    fn push_marker(&mut self) {
+        self.words.push(0);
+        self.marker = self.words.len() - 1;
+    }
+
+    fn add_literal(&mut self, word: u64) {
+        self.set_literal_words(self.literal_words() + 1);
+        self.words.push(word);
+    }
+
+    fn add_empty_word(&mut self, bit: bool) {
+        let no_literal = self.literal_words() == 0;
+        let running_len = self.running_len();
+        if no_literal && running_len == 0 {
+            self.set_run_bit(bit);
+        }
+        if no_literal && self.run_bit() == bit {
+            self.set_running_len(running_len + 1);
+        } else {
+            self.push_marker();
+            self.set_run_bit(bit);
+            self.set_running_len(1);
+        }
+    }
+
+    fn add_empty_words(&mut self, bit: bool, count: u64) {
+        if self.run_bit() != bit && self.running_len() + self.literal_words() == 0 {
+            self.set_run_bit(bit);
+        } else if self.literal_words() != 0 || self.run_bit() != bit {
+            self.push_marker();
+            self.set_run_bit(bit);
+        }
+        self.set_running_len(self.running_len() + count);
+    }
+}
+
+/// Where the entries end and each extension lies in an index file
+struct IndexLayout {
+    entries_end: usize,
+    extensions: Vec<([u8; 4], std::ops::Range<usize>)>,
+    hash_len: usize,
+}
+
+impl IndexLayout {
+    fn parse(data: &[u8], hash_kind: gix_hash::Kind) -> Option<Self> {
+        let hash_len = hash_kind.len_in_bytes();
+        let body_end = data.len().checked_sub(hash_len)?;
+        let mut reader = Reader(&data[..body_end]);
+        if reader.take(4)? != b"DIRC" {
+            return None;
+        }
+        let version = reader.u32()?;
+        let entries = reader.u32()?;
+        for _ in 0..entries {
+            let start = body_end - reader.0.len();
+            // stat data and mode, then the id and flags
+            reader.take(40 + hash_len)?;
+            let flags = u16::from_be_bytes(reader.take(2)?.try_into().ok()?);
+            if version >= 3 && flags & 0x4000 != 0 {
+                reader.take(2)?;
+            }
+            if version >= 4 {
+                // prefix-compressed, no padding
+                reader.varint()?;
+                reader.until_nul()?;
+            } else {
+                let name_start = body_end - reader.0.len();
+                let name_len = reader.0.find_byte(0)?;
+                // NUL padded to a multiple of eight
+                let len = (name_start - start + name_len + 8) & !7;
+                reader.take(len - (name_start - start))?;
+            }
+        }
+
+        let entries_end = body_end - reader.0.len();
+        let mut extensions = Vec::new();
+        while !reader.0.is_empty() {
+            let signature: [u8; 4] = reader.take(4)?.try_into().ok()?;
+            let len = reader.u32()? as usize;
+            let start = body_end - reader.0.len();
+            reader.take(len)?;
+            extensions.push((signature, start..start + len));
+        }
+        Some(Self {
+            entries_end,
+            extensions,
+            hash_len,
+        })
+    }
+}
+
+/// Store `cache` in the index file at `path`, which was `original` when it was
+/// read. Nothing is written if someone else holds the index lock or the file
+/// changed in the meantime; the cache is only worth updating opportunistically.
+///
+/// With `keep_mtime` the index keeps its modification time, for entries that
+/// are racy by it to stay so; the entries themselves aren't touched.
+/// `shared_permissions` is `core.sharedRepository` as [`shared_repository_permissions`](crate::shared_repository_permissions)
+/// reads it.
+pub fn write_to_index(
+    path: &Path,
+    original: &[u8],
+    cache: &UntrackedCache,
+    hash_kind: gix_hash::Kind,
+    keep_mtime: bool,
+    shared_permissions: i32,
+) -> Result<bool> {
+    let Some(layout) = IndexLayout::parse(original, hash_kind) else {
+        return Ok(false);
+    };
+    let Ok(mut lock) = gix::lock::File::acquire_to_update_resource(
+        path,
+        gix::lock::acquire::Fail::Immediately,
+        None,
+        shared_permissions,
+    ) else {
+        return Ok(false);
+    };
+    let meta = std::fs::metadata(path)?;
+    if std::fs::read(path)? != original {
+        return Ok(false);
+    }
+
+    let encoded = cache.encode(layout.hash_len);
+    let mut extensions: Vec<(&[u8; 4], &[u8])> = Vec::new();
+    let mut placed = false;
+    for (signature, range) in &layout.extensions {
+        match signature {
+            s if s == SIGNATURE => {
+                extensions.push((SIGNATURE, &encoded));
+                placed = true;
+            }
+            // rewritten below, to cover the new extension sizes
+            s if s == END_OF_INDEX_ENTRY => {}
+            s => extensions.push((s, &original[range.clone()])),
+        }
+    }
+    if !placed {
+        // git writes it after TREE, REUC and the split index link, before FSMN
+        let at = extensions
+            .iter()
+            .position(|(s, _)| !matches!(*s, b"TREE" | b"REUC" | b"link" | b"IEOT"))
+            .unwrap_or(extensions.len());
+        extensions.insert(at, (SIGNATURE, &encoded));
+    }
+
+    let mut out = original[..layout.entries_end].to_vec();
+    let mut toc = gix::hash::hasher(hash_kind);
+    for (signature, data) in &extensions {
+        out.extend_from_slice(*signature);
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        out.extend_from_slice(data);
+        toc.update(*signature);
+        toc.update(&(data.len() as u32).to_be_bytes());
+    }
+    let had_eoie = layout
+        .extensions
+        .iter()
+        .any(|(signature, _)| signature == END_OF_INDEX_ENTRY);
+    if had_eoie {
+        out.extend_from_slice(END_OF_INDEX_ENTRY);
+        out.extend_from_slice(&(4 + layout.hash_len as u32).to_be_bytes());
+        out.extend_from_slice(&(layout.entries_end as u32).to_be_bytes());
+        out.extend_from_slice(toc.try_finalize()?.as_bytes());
+    }
+    // an index written with `index.skipHash` keeps its null checksum
+    let trailer = &original[original.len() - layout.hash_len..];
+    if trailer.iter().all(|&b| b == 0) {
+        out.extend_from_slice(trailer);
+    } else {
+        let mut hasher = gix::hash::hasher(hash_kind);
+        hasher.update(&out);
+        out.extend_from_slice(hasher.try_finalize()?.as_bytes());
+    }
+
+    lock.write_all(&out)?;
+    let (_, file) = lock
+        .commit()
+        .map_err(|e| e.error)
+        .with_context(|| format!("Failed to write '{}'", path.display()))?;
+    if keep_mtime {
+        let file = match file {
+            Some(file) => file,
+            None => std::fs::File::options().write(true).open(path)?,
+        };
+        file.set_modified(meta.modified()?)?;
+    }
+    Ok(true)
+}
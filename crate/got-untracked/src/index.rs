@@ -20,6 +20,7 @@ pub fn get_index_entries(repo: &Repository) -> Result<Vec<IndexedFile>> {
                 oid: e.id,
             })
         })
+        .map(|(_, file)| Ok(file))
         .collect()
 }
 
@@ -27,8 +28,9 @@ pub fn get_index_entries(repo: &Repository) -> Result<Vec<IndexedFile>> {
 pub fn path_in_index(repo: &Repository, path: &BStr) -> Result<bool> {
     let index = repo.index()?;
 
-    Ok(index
+    let found = index
         .entries_with_paths_by_filter_map(|p, _e| if p == path { Some(()) } else { None })
         .next()
-        .is_some())
+        .is_some();
+    Ok(found)
 }
@@ -1,9 +1,10 @@
-pub mod repo;
+pub mod extension;
 pub mod index;
-pub mod untracked;
+pub mod repo;
 pub mod types;
+pub mod untracked;
 
-pub use repo::*;
 pub use index::*;
-pub use untracked::*;
+pub use repo::*;
 pub use types::*;
+pub use untracked::*;
@@ -1,21 +1,60 @@
 use anyhow::Result;
+use gix::bstr::ByteSlice;
 use gix::Repository;
 
+use crate::types::UntrackedFilter;
+
 /// Open a repository with proper error handling
 pub fn open_repo(path: &str) -> Result<Repository> {
     gix::open(path).map_err(|e| anyhow::anyhow!("Failed to open git repository '{}': {}", path, e))
 }
 
 /// Get HEAD commit, falling back to empty tree for empty repos
-pub fn get_head_tree(repo: &Repository) -> Result<gix::Tree> {
+pub fn get_head_tree(repo: &Repository) -> Result<gix::Tree<'_>> {
     match repo.head_commit() {
         Ok(commit) => commit
             .tree()
             .map_err(|e| anyhow::anyhow!("Failed to get HEAD tree: {}", e)),
         Err(_) => {
-            let oid = gix_hash::ObjectId::empty_tree(gix::hash::Kind::Sha1);
+            let oid = gix_hash::ObjectId::empty_tree(repo.object_hash());
             repo.find_tree(oid)
                 .map_err(|e| anyhow::anyhow!("Failed to find empty tree: {}", e))
         }
     }
 }
+
+/// How index stat data is compared with the worktree, from `core.trustCTime`
+/// and `core.checkStat` like git does
+pub fn stat_options(repo: &Repository) -> gix::index::entry::stat::Options {
+    let config = repo.config_snapshot();
+    gix::index::entry::stat::Options {
+        trust_ctime: config.boolean("core.trustCTime").unwrap_or(true),
+        check_stat: config
+            .string("core.checkStat")
+            .is_none_or(|v| v != "minimal"),
+        use_nsec: config.boolean("gitoxide.core.useNsec").unwrap_or(false),
+        use_stdev: config.boolean("gitoxide.core.useStdev").unwrap_or(false),
+    }
+}
+
+/// The untracked files mode `status.showUntrackedFiles` sets, `Normal` when
+/// unset like git
+pub fn configured_untracked(repo: &Repository) -> UntrackedFilter {
+    repo.config_snapshot()
+        .string("status.showUntrackedFiles")
+        .and_then(|value| UntrackedFilter::from_config(value.to_str().ok()?))
+        .unwrap_or_default()
+}
+
+/// `core.sharedRepository` in the encoding `gix::lock` takes, for files written
+/// into the git dir: `0` leaves permissions to the umask
+pub fn shared_repository_permissions(repo: &Repository) -> i32 {
+    repo.config_snapshot()
+        .string("core.sharedRepository")
+        .and_then(|value| {
+            gix::config::tree::Core::SHARED_REPOSITORY
+                .try_into_shared_repository(Some(value))
+                .ok()
+        })
+        .unwrap_or(0)
+}
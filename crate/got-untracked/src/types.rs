@@ -1,17 +1,19 @@
-use gix_hash::ObjectId;
+use gix::bstr::BString;
+use std::path::PathBuf;
 
 /// Git status character
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
 pub enum StatusChar {
-    Modified = 'M',
-    Added = 'A',
-    Deleted = 'D',
-    Renamed = 'R',
-    Copied = 'C',
-    Unmerged = 'U',
-    Untracked = '?',
-    Ignored = '!',
-    None = ' ',
+    Modified = b'M',
+    Added = b'A',
+    Deleted = b'D',
+    Renamed = b'R',
+    Copied = b'C',
+    Unmerged = b'U',
+    Untracked = b'?',
+    Ignored = b'!',
+    None = b' ',
 }
 
 impl From<StatusChar> for char {
@@ -21,7 +23,7 @@ impl From<StatusChar> for char {
 }
 
 /// Untracked file filter options
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum UntrackedFilter {
     /// Show no untracked files
     No,
@@ -32,12 +34,74 @@ pub enum UntrackedFilter {
     All,
 }
 
+impl UntrackedFilter {
+    /// Parse a `status.showUntrackedFiles` / `git status -u<mode>` value
+    pub fn from_config(value: &str) -> Option<Self> {
+        match value {
+            "no" | "false" => Some(Self::No),
+            "normal" | "true" => Some(Self::Normal),
+            "all" => Some(Self::All),
+            _ => None,
+        }
+    }
+}
+
+/// Which ignored paths to report, as in `git status --ignored=<mode>`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum IgnoredFilter {
+    #[default]
+    No,
+    /// Ignored files, and directories holding nothing but ignored files as
+    /// `dir/`; every file inside them with `UntrackedFilter::All`
+    Traditional,
+    /// Only paths an ignore pattern matches, directories as `dir/`
+    Matching,
+}
+
+impl IgnoredFilter {
+    /// Parse a `git status --ignored=<mode>` value
+    pub fn from_arg(value: &str) -> Option<Self> {
+        match value {
+            "no" => Some(IgnoredFilter::No),
+            "traditional" => Some(IgnoredFilter::Traditional),
+            "matching" => Some(IgnoredFilter::Matching),
+            _ => None,
+        }
+    }
+}
+
 /// Configuration for status operations
 #[derive(Debug, Clone, Default)]
 pub struct StatusOptions {
     pub untracked: UntrackedFilter,
-    pub show_ignored: bool,
-    pub recurse_submodules: bool,
+    pub ignored: IgnoredFilter,
+    /// Only walk this directory, relative to the worktree root
+    pub subdir: Option<PathBuf>,
+    /// Only report paths these git pathspecs take in, as `git status --
+    /// <pathspec>` does
+    pub pathspec: Vec<BString>,
+    /// Use the index's untracked cache without writing it back, for callers
+    /// that watch the index and would see their own write as a change
+    pub keep_index: bool,
+}
+
+/// A path the walk turned up, relative to the worktree root; a directory
+/// reported as a whole ends in `/`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UntrackedPath {
+    pub path: BString,
+    /// Matched by the ignore rules rather than untracked
+    pub ignored: bool,
+}
+
+/// What `untracked_files` found
+#[derive(Debug, Clone, Default)]
+pub struct Untracked {
+    /// Untracked paths sorted, then ignored ones sorted
+    pub paths: Vec<UntrackedPath>,
+    /// Repositories nested in the worktree, as `dir/`, that the pathspec only
+    /// matches paths inside of, so they aren't reported themselves
+    pub repos_matched_inside: Vec<BString>,
 }
 
 /// Represents status of a file in git
@@ -0,0 +1,751 @@
+use anyhow::Result;
+use gix::bstr::{BStr, BString, ByteSlice};
+use gix::index::entry::{stat, Stat};
+use gix::worktree::stack::state::attributes::Source as AttributesSource;
+use gix::worktree::stack::state::ignore::Source as IgnoreSource;
+use gix::Repository;
+use gix_hash::ObjectId;
+use std::path::{Path, PathBuf};
+
+use crate::extension::{
+    write_to_index, CachedDir, OidStat, UntrackedCache, DIR_HIDE_EMPTY_DIRECTORIES,
+    DIR_SHOW_OTHER_DIRECTORIES,
+};
+use crate::types::{IgnoredFilter, StatusOptions, Untracked, UntrackedFilter, UntrackedPath};
+
+/// Untracked paths in the worktree of `repo`, relative to its root and sorted:
+/// files, and directories as `dir/`. With `UntrackedFilter::Normal` a directory
+/// without tracked files is one `dir/` entry if it holds any untracked file, and
+/// a repository nested in the worktree is always one.
+///
+/// Paths matching `.gitignore`, `info/exclude` or `core.excludesFile` are left
+/// out, or reported after the untracked ones as `options.ignored` asks. A
+/// `subdir` or `pathspec` narrows the walk as `git status` narrows it.
+/// `index` has to be the index of `repo` as it is on disk.
+///
+/// Like `git status`, the walk goes through the index's untracked cache when
+/// `core.untrackedCache` allows it: a directory whose stat data and
+/// `.gitignore` are unchanged isn't read again. The cache is written back into
+/// the index afterwards if the walk changed it and nobody holds the index lock,
+/// unless `options.keep_index` is set.
+/// The cache only covers whole-worktree walks without ignored paths, so any
+/// of the other options bypasses it.
+pub fn untracked_files(
+    repo: &Repository,
+    index: &gix::index::File,
+    options: &StatusOptions,
+) -> Result<Untracked> {
+    if options.untracked == UntrackedFilter::No {
+        return Ok(Untracked::default());
+    }
+    let work_dir = repo
+        .workdir()
+        .ok_or_else(|| anyhow::anyhow!("Repository has no working directory"))?
+        .to_path_buf();
+    let pathspec = if options.pathspec.is_empty() {
+        None
+    } else {
+        let spec = repo.pathspec(
+            false,
+            &options.pathspec,
+            true,
+            index,
+            AttributesSource::WorktreeThenIdMapping,
+        )?;
+        Some(spec.detach()?)
+    };
+    let mut start = BString::default();
+    if let Some(ref subdir) = options.subdir {
+        start = gix::path::to_unix_separators_on_windows(gix::path::into_bstr(subdir.as_path())?)
+            .into_owned();
+        if !start.is_empty() && !start.ends_with(b"/") {
+            start.push(b'/');
+        }
+    }
+
+    let mut walk = Walk {
+        repo,
+        index,
+        excludes: repo
+            .excludes(index, None, IgnoreSource::WorktreeThenIdMappingIfNotSkipped)?
+            .detach(),
+        work_dir,
+        mode: options.untracked,
+        ignored: options.ignored,
+        pathspec,
+        stat_options: crate::stat_options(repo),
+        cache: None,
+        reread: false,
+        found: Vec::new(),
+        repos_matched_inside: Vec::new(),
+    };
+
+    let whole_worktree =
+        options.ignored == IgnoredFilter::No && walk.pathspec.is_none() && start.is_empty();
+    let index_data = if whole_worktree {
+        std::fs::read(index.path()).ok()
+    } else {
+        None
+    };
+    // only a cache that goes with the index in memory
+    let index_data =
+        index_data.filter(|data| checksum(data, repo.object_hash()) == index.checksum());
+    let before = index_data.as_deref().and_then(|data| walk.load_cache(data));
+    let root = walk.cache.as_ref().map(|_| 0);
+    walk.read_dir(start.as_bstr(), root, false)?;
+
+    if let (Some(cache), Some(data), false) = (&walk.cache, &index_data, options.keep_index) {
+        // like git, also when only stat data was refreshed, so that a newer
+        // index stops directories looking racy
+        if walk.reread || before.as_ref() != Some(&cache.encode(repo.object_hash().len_in_bytes()))
+        {
+            // a newer index would make entries that are racy now look clean
+            let keep_mtime = index
+                .entries()
+                .iter()
+                .any(|entry| entry.stat.is_racy(index.timestamp(), walk.stat_options));
+            // a stale cache only costs time, and git writes it again anyway
+            let _ = write_to_index(
+                index.path(),
+                data,
+                cache,
+                repo.object_hash(),
+                keep_mtime,
+                crate::shared_repository_permissions(repo),
+            );
+        }
+    }
+
+    let mut paths = walk.found;
+    paths.sort_by(|a, b| (a.ignored, &a.path).cmp(&(b.ignored, &b.path)));
+    Ok(Untracked {
+        paths,
+        repos_matched_inside: walk.repos_matched_inside,
+    })
+}
+
+struct Walk<'a> {
+    repo: &'a Repository,
+    index: &'a gix::index::File,
+    excludes: gix::worktree::Stack,
+    work_dir: PathBuf,
+    mode: UntrackedFilter,
+    ignored: IgnoredFilter,
+    pathspec: Option<gix::PathspecDetached>,
+    stat_options: stat::Options,
+    /// In use for this walk, with `dirs[0]` as the root
+    cache: Option<UntrackedCache>,
+    /// A cached directory had to be read again
+    reread: bool,
+    found: Vec<UntrackedPath>,
+    repos_matched_inside: Vec<BString>,
+}
+
+/// What walking a directory without tracked files turned up, when ignored
+/// paths are wanted
+#[derive(Default)]
+struct Scan {
+    untracked: bool,
+    ignored: Vec<UntrackedPath>,
+}
+
+/// How `core.untrackedCache` asks for the cache to be treated
+#[derive(PartialEq)]
+enum CacheMode {
+    /// Use it where git left one
+    Keep,
+    /// Use it, starting one if there is none
+    Enable,
+    Disable,
+}
+
+impl Walk<'_> {
+    /// Take up the index's untracked cache if it applies to this walk, or start
+    /// one if configured to; returns its encoding as found, `None` for a new one
+    fn load_cache(&mut self, index_data: &[u8]) -> Option<Vec<u8>> {
+        let config = self.repo.config_snapshot();
+        let mode = match config.string("core.untrackedCache") {
+            Some(value) => match value.to_str_lossy().to_ascii_lowercase().as_str() {
+                "keep" => CacheMode::Keep,
+                "false" | "no" | "off" | "0" => CacheMode::Disable,
+                _ => CacheMode::Enable,
+            },
+            None if config.boolean("feature.manyFiles") == Some(true) => CacheMode::Enable,
+            None => CacheMode::Keep,
+        };
+        if mode == CacheMode::Disable {
+            return None;
+        }
+
+        // entries are only good for the kind of walk they were made by, and
+        // git only keeps them for the one `status.showUntrackedFiles` asks for
+        let flags = dir_flags(self.mode);
+        if flags != dir_flags(crate::configured_untracked(self.repo)) {
+            return None;
+        }
+
+        let ident = ident(&self.work_dir);
+        let hash_kind = self.repo.object_hash();
+        let found = UntrackedCache::from_index(index_data, hash_kind)
+            .filter(|cache| cache.ident == ident && cache.exclude_per_dir == ".gitignore");
+        let before = found
+            .as_ref()
+            .map(|cache| cache.encode(hash_kind.len_in_bytes()));
+        let mut cache = match found {
+            Some(cache) => cache,
+            None if mode == CacheMode::Enable => UntrackedCache {
+                ident,
+                info_exclude: OidStat::default(),
+                excludes_file: OidStat::default(),
+                dir_flags: flags,
+                exclude_per_dir: ".gitignore".into(),
+                dirs: Vec::new(),
+            },
+            None => return None,
+        };
+        if cache.dir_flags != flags {
+            cache.dir_flags = flags;
+            cache.dirs.clear();
+        }
+        if cache.dirs.is_empty() {
+            cache.dirs.push(CachedDir::default());
+        }
+
+        // every entry depends on the repository-wide exclude files
+        let info_exclude = self.oid_stat(&self.repo.common_dir().join("info").join("exclude"));
+        let excludes_file = match excludes_file(self.repo) {
+            Some(path) => self.oid_stat(&path),
+            None => OidStat::default(),
+        };
+        if info_exclude.id != cache.info_exclude.id || excludes_file.id != cache.excludes_file.id {
+            invalidate(&mut cache.dirs, 0);
+        }
+        cache.info_exclude = info_exclude;
+        cache.excludes_file = excludes_file;
+        cache.dirs[0].recurse = true;
+
+        self.cache = Some(cache);
+        before
+    }
+
+    /// Untracked entries in the directory `rel` (`""` or ending in `/`), which
+    /// is cache node `node` when the cache is in use. In `check_only` mode
+    /// nothing is reported; the walk stops at the first untracked file and
+    /// returns whether there is one.
+    fn read_dir(&mut self, rel: &BStr, node: Option<usize>, check_only: bool) -> Result<bool> {
+        let dir = self.work_dir.join(gix::path::from_bstr(rel)?);
+        if let Some(node) = node {
+            if self.cached_dir_is_valid(rel, &dir, node, check_only) {
+                return self.replay(rel, node, check_only);
+            }
+            self.reread = true;
+        }
+
+        let mut found = false;
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            return Ok(false);
+        };
+        for entry in entries {
+            let Ok(entry) = entry else { continue };
+            let name = gix::path::os_string_into_bstring(entry.file_name())?;
+            // only `.git` itself is off limits, here or in a nested repository;
+            // other dotfiles go through the ignore rules like anything else
+            if name == ".git" {
+                continue;
+            }
+            let mut rel_path = rel.to_owned();
+            rel_path.extend_from_slice(&name);
+            let path = entry.path();
+            // a symlink to a directory is a single untracked entry, like git sees it
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+
+            // a directory in place of a tracked file is walked with `All` but
+            // passed over with `Normal`, as git does; a submodule never is
+            if let Ok(idx) = self.index.entry_index_by_path(rel_path.as_bstr()) {
+                if !is_dir
+                    || self.index.entries()[idx].mode.is_submodule()
+                    || self.mode == UntrackedFilter::Normal
+                {
+                    continue;
+                }
+            }
+            if self.is_excluded(rel_path.as_bstr(), is_dir)? {
+                if !check_only {
+                    self.report_ignored(rel_path, &path, is_dir)?;
+                }
+                continue;
+            }
+
+            let untracked = if is_dir {
+                let mut dir_rel = rel_path.clone();
+                dir_rel.push(b'/');
+                let has_tracked = self.index.prefixed_entries(dir_rel.as_bstr()).is_some();
+                if !has_tracked && is_nested_repo(&path) {
+                    // a repository of its own is a single `dir/`, whatever the
+                    // untracked filter
+                    if !self.included(rel_path.as_bstr(), true) {
+                        if self.can_match(rel_path.as_bstr()) {
+                            self.repos_matched_inside.push(dir_rel);
+                        }
+                        continue;
+                    }
+                    true
+                } else if !has_tracked && check_only {
+                    let child = node.map(|node| self.child(node, name.as_bstr()));
+                    self.read_dir(dir_rel.as_bstr(), child, true)?
+                } else if !has_tracked
+                    && self.mode == UntrackedFilter::Normal
+                    && self.included(rel_path.as_bstr(), true)
+                {
+                    // one `dir/` entry, if there is anything untracked inside
+                    if self.ignored == IgnoredFilter::No {
+                        let child = node.map(|node| self.child(node, name.as_bstr()));
+                        self.read_dir(dir_rel.as_bstr(), child, true)?
+                    } else {
+                        let scan = self.scan_dir(dir_rel.as_bstr())?;
+                        if !scan.untracked
+                            && self.ignored == IgnoredFilter::Traditional
+                            && !scan.ignored.is_empty()
+                        {
+                            // nothing but ignored files: one entry for them all
+                            self.found.push(UntrackedPath {
+                                path: dir_rel,
+                                ignored: true,
+                            });
+                        } else {
+                            self.found.extend(scan.ignored);
+                        }
+                        scan.untracked
+                    }
+                } else {
+                    if !self.can_match(rel_path.as_bstr()) {
+                        continue;
+                    }
+                    let child = node.map(|node| self.child(node, name.as_bstr()));
+                    found |= self.read_dir(dir_rel.as_bstr(), child, check_only)?;
+                    if check_only && found {
+                        break;
+                    }
+                    continue;
+                }
+            } else {
+                // a check for `dir/` counts only files the pathspec takes in,
+                // as git's does
+                self.included(rel_path.as_bstr(), false)
+            };
+            if !untracked {
+                continue;
+            }
+
+            found = true;
+            let mut entry_name = name;
+            if is_dir {
+                entry_name.push(b'/');
+            }
+            if let (Some(node), Some(cache)) = (node, self.cache.as_mut()) {
+                cache.dirs[node].untracked.push(entry_name.clone());
+            }
+            if check_only {
+                break;
+            }
+            let mut path = rel.to_owned();
+            path.extend_from_slice(&entry_name);
+            self.found.push(UntrackedPath {
+                path,
+                ignored: false,
+            });
+        }
+
+        if let (Some(node), Some(cache)) = (node, self.cache.as_mut()) {
+            let dir = &mut cache.dirs[node];
+            dir.valid = true;
+            dir.recurse = true;
+        }
+        Ok(found)
+    }
+
+    /// Report the ignored path `rel_path` as `self.ignored` asks: a file as it
+    /// is, a directory as `dir/` or, in traditional mode with all untracked
+    /// files, as every file inside it
+    fn report_ignored(&mut self, rel_path: BString, path: &Path, is_dir: bool) -> Result<()> {
+        if self.ignored == IgnoredFilter::No {
+            return Ok(());
+        }
+        if !is_dir {
+            if self.included(rel_path.as_bstr(), false) {
+                self.found.push(UntrackedPath {
+                    path: rel_path,
+                    ignored: true,
+                });
+            }
+            return Ok(());
+        }
+        if self.mode == UntrackedFilter::All && self.ignored == IgnoredFilter::Traditional {
+            let files = self.ignored_files(rel_path.as_bstr(), path)?;
+            self.found.extend(files);
+            return Ok(());
+        }
+        // under a pathspec, as git has it: when a pattern takes the directory
+        // in whole, or when one could match inside it and (in traditional
+        // mode) actually does
+        let reported = self.included(rel_path.as_bstr(), true)
+            || (self.can_match(rel_path.as_bstr())
+                && (self.ignored == IgnoredFilter::Matching
+                    || !self.ignored_files(rel_path.as_bstr(), path)?.is_empty()));
+        if reported {
+            let mut dir_rel = rel_path;
+            dir_rel.push(b'/');
+            self.found.push(UntrackedPath {
+                path: dir_rel,
+                ignored: true,
+            });
+        }
+        Ok(())
+    }
+
+    /// Every file under the ignored directory `rel_path` that the pathspec
+    /// takes in, sorted. Nested repositories stay whole, as `dir/`.
+    fn ignored_files(&mut self, rel_path: &BStr, path: &Path) -> Result<Vec<UntrackedPath>> {
+        let mut files = Vec::new();
+        for_each_file(rel_path, path, &mut |rel_path, is_repo| {
+            files.push((rel_path, is_repo))
+        })?;
+        files.sort();
+        let mut ignored = Vec::new();
+        for (mut rel_path, is_repo) in files {
+            if !self.included(rel_path.as_bstr(), is_repo) {
+                continue;
+            }
+            if is_repo {
+                rel_path.push(b'/');
+            }
+            ignored.push(UntrackedPath {
+                path: rel_path,
+                ignored: true,
+            });
+        }
+        Ok(ignored)
+    }
+
+    /// Walk the directory `rel` (ending in `/`), which holds no tracked files,
+    /// for whether anything in it is untracked and which ignored paths to
+    /// report: in traditional mode a subdirectory of nothing but ignored
+    /// files is one `dir/`
+    fn scan_dir(&mut self, rel: &BStr) -> Result<Scan> {
+        let mut scan = Scan::default();
+        let dir = self.work_dir.join(gix::path::from_bstr(rel)?);
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            return Ok(scan);
+        };
+        for entry in entries {
+            let Ok(entry) = entry else { continue };
+            let name = gix::path::os_string_into_bstring(entry.file_name())?;
+            if name == ".git" {
+                continue;
+            }
+            let mut rel_path = rel.to_owned();
+            rel_path.extend_from_slice(&name);
+            let path = entry.path();
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+
+            if self.is_excluded(rel_path.as_bstr(), is_dir)? {
+                if is_dir {
+                    rel_path.push(b'/');
+                }
+                scan.ignored.push(UntrackedPath {
+                    path: rel_path,
+                    ignored: true,
+                });
+            } else if is_dir && !is_nested_repo(&path) {
+                let mut dir_rel = rel_path;
+                dir_rel.push(b'/');
+                let sub = self.scan_dir(dir_rel.as_bstr())?;
+                if sub.untracked || self.ignored == IgnoredFilter::Matching {
+                    scan.untracked |= sub.untracked;
+                    scan.ignored.extend(sub.ignored);
+                } else if !sub.ignored.is_empty() {
+                    scan.ignored.push(UntrackedPath {
+                        path: dir_rel,
+                        ignored: true,
+                    });
+                }
+            } else {
+                scan.untracked = true;
+            }
+        }
+        Ok(scan)
+    }
+
+    /// Whether the pathspec, if any, takes in `rel_path` itself
+    fn included(&mut self, rel_path: &BStr, is_dir: bool) -> bool {
+        self.pathspec
+            .as_mut()
+            .is_none_or(|spec| spec.is_included(rel_path, Some(is_dir)))
+    }
+
+    /// Whether the pathspec, if any, could take in anything inside the
+    /// directory `rel_path`
+    fn can_match(&mut self, rel_path: &BStr) -> bool {
+        self.pathspec
+            .as_mut()
+            .is_none_or(|spec| spec.search.can_match_relative_path(rel_path, Some(true)))
+    }
+
+    /// Whether node `node` still describes `dir` as it is. A stale node is
+    /// reset, taking the directory's current stat data, for a fresh read.
+    fn cached_dir_is_valid(
+        &mut self,
+        rel: &BStr,
+        dir: &Path,
+        node: usize,
+        check_only: bool,
+    ) -> bool {
+        let mut gitignore = rel.to_owned();
+        gitignore.extend_from_slice(b".gitignore");
+        let exclude_oid = self.exclude_id(&dir.join(".gitignore"), Some(gitignore.as_bstr()));
+        let stat = gix::index::fs::Metadata::from_path_no_follow(dir)
+            .ok()
+            .and_then(|meta| Stat::from_fs(&meta).ok())
+            .unwrap_or_default();
+        let timestamp = self.index.timestamp();
+        let stat_options = self.stat_options;
+        let Some(cache) = self.cache.as_mut() else {
+            return false;
+        };
+
+        // a changed `.gitignore` changes what is untracked all the way down
+        if cache.dirs[node].exclude_oid != exclude_oid {
+            invalidate(&mut cache.dirs, node);
+            cache.dirs[node].exclude_oid = exclude_oid;
+        }
+        let cached = &mut cache.dirs[node];
+        cached.recurse = true;
+        if cached.valid
+            && cached.check_only == check_only
+            && cached.stat.matches(&stat, stat_options)
+            && !cached.stat.is_racy(timestamp, stat_options)
+        {
+            return true;
+        }
+        cached.stat = stat;
+        cached.valid = false;
+        cached.check_only = check_only;
+        cached.untracked.clear();
+        let children = cached.children.clone();
+        for child in children {
+            cache.dirs[child].recurse = false;
+        }
+        false
+    }
+
+    /// Report what a valid node holds, looking into its subdirectories again
+    fn replay(&mut self, rel: &BStr, node: usize, check_only: bool) -> Result<bool> {
+        let Some(cache) = self.cache.as_ref() else {
+            return Ok(false);
+        };
+        let children: Vec<(usize, BString, bool)> = cache.dirs[node]
+            .children
+            .iter()
+            .filter(|&&child| cache.dirs[child].recurse)
+            .map(|&child| {
+                let dir = &cache.dirs[child];
+                (child, dir.name.clone(), dir.check_only)
+            })
+            .collect();
+        let untracked = cache.dirs[node].untracked.clone();
+
+        let mut found = false;
+        let mut checked = Vec::new();
+        for (child, name, child_check_only) in children {
+            let mut dir_rel = rel.to_owned();
+            dir_rel.extend_from_slice(&name);
+            dir_rel.push(b'/');
+            if child_check_only && !check_only {
+                // an untracked directory: whether it still holds anything
+                checked.push(dir_rel.clone());
+                if self.read_dir(dir_rel.as_bstr(), Some(child), true)? {
+                    found = true;
+                    self.found.push(UntrackedPath {
+                        path: dir_rel,
+                        ignored: false,
+                    });
+                }
+            } else {
+                found |= self.read_dir(dir_rel.as_bstr(), Some(child), check_only)?;
+            }
+            if check_only && found {
+                return Ok(true);
+            }
+        }
+        for name in untracked {
+            let mut path = rel.to_owned();
+            path.extend_from_slice(&name);
+            if checked.contains(&path) {
+                continue;
+            }
+            found = true;
+            if check_only {
+                break;
+            }
+            self.found.push(UntrackedPath {
+                path,
+                ignored: false,
+            });
+        }
+        Ok(found)
+    }
+
+    /// The node for subdirectory `name` of `node`, made if there is none yet
+    fn child(&mut self, node: usize, name: &BStr) -> usize {
+        let cache = self.cache.as_mut().expect("nodes only exist with a cache");
+        let children = &cache.dirs[node].children;
+        match children.binary_search_by(|&child| cache.dirs[child].name.as_bstr().cmp(name)) {
+            Ok(pos) => children[pos],
+            Err(pos) => {
+                let child = cache.dirs.len();
+                cache.dirs.push(CachedDir {
+                    name: name.to_owned(),
+                    ..Default::default()
+                });
+                cache.dirs[node].children.insert(pos, child);
+                child
+            }
+        }
+    }
+
+    fn is_excluded(&mut self, rel_path: &BStr, is_dir: bool) -> Result<bool> {
+        let mode = is_dir.then_some(gix::index::entry::Mode::DIR);
+        let platform = self.excludes.at_entry(rel_path, mode, &self.repo.objects)?;
+        Ok(platform.is_excluded())
+    }
+
+    /// The id git gives the exclude file at `path`, `rel_path` within the
+    /// worktree: that of its index entry while it is unchanged, or else of its
+    /// content with a newline appended, as git reads it. `None` if it can't be read.
+    fn exclude_id(&self, path: &Path, rel_path: Option<&BStr>) -> Option<ObjectId> {
+        let meta = gix::index::fs::Metadata::from_path_no_follow(path).ok()?;
+        let hash_kind = self.repo.object_hash();
+        if let Some(entry) = rel_path.and_then(|rel| self.index.entry_by_path(rel)) {
+            let unchanged = Stat::from_fs(&meta).is_ok_and(|stat| {
+                entry.stat.matches(&stat, self.stat_options)
+                    && !entry
+                        .stat
+                        .is_racy(self.index.timestamp(), self.stat_options)
+            });
+            if unchanged {
+                return Some(entry.id);
+            }
+        }
+        let mut data = std::fs::read(path).ok()?;
+        if data.is_empty() {
+            return Some(ObjectId::empty_blob(hash_kind));
+        }
+        data.push(b'\n');
+        gix::objs::compute_hash(hash_kind, gix::objs::Kind::Blob, &data).ok()
+    }
+
+    fn oid_stat(&self, path: &Path) -> OidStat {
+        OidStat {
+            stat: gix::index::fs::Metadata::from_path_no_follow(path)
+                .ok()
+                .and_then(|meta| Stat::from_fs(&meta).ok())
+                .unwrap_or_default(),
+            id: self.exclude_id(path, None),
+        }
+    }
+}
+
+/// Mark `node` and everything below it as needing a fresh read
+fn invalidate(dirs: &mut [CachedDir], node: usize) {
+    let dir = &mut dirs[node];
+    dir.valid = false;
+    dir.untracked.clear();
+    for child in dir.children.clone() {
+        invalidate(dirs, child);
+    }
+}
+
+fn dir_flags(mode: UntrackedFilter) -> u32 {
+    match mode {
+        UntrackedFilter::All => 0,
+        _ => DIR_SHOW_OTHER_DIRECTORIES | DIR_HIDE_EMPTY_DIRECTORIES,
+    }
+}
+
+/// What git stamps its cache with, so it is dropped when the worktree moves
+/// or the repository is shared with another system
+fn ident(work_dir: &Path) -> BString {
+    let work_dir = work_dir
+        .canonicalize()
+        .unwrap_or_else(|_| work_dir.to_path_buf());
+    // `uname -s`, which is what git reads
+    let system = match std::env::consts::OS {
+        "linux" | "android" => "Linux",
+        "macos" | "ios" => "Darwin",
+        "freebsd" => "FreeBSD",
+        "netbsd" => "NetBSD",
+        "openbsd" => "OpenBSD",
+        "windows" => "Windows",
+        other => other,
+    };
+    let mut ident = BString::from(format!(
+        "Location {}, system {}",
+        work_dir.display(),
+        system
+    ));
+    ident.push(0);
+    ident
+}
+
+/// `core.excludesFile`, or `$XDG_CONFIG_HOME/git/ignore` when unset
+fn excludes_file(repo: &Repository) -> Option<PathBuf> {
+    let config = repo.config_snapshot();
+    if let Some(path) = config.trusted_path("core.excludesFile").transpose() {
+        return path.ok();
+    }
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("git").join("ignore"))
+}
+
+/// The checksum trailing the index file `data`, as `gix` reports it
+fn checksum(data: &[u8], hash_kind: gix_hash::Kind) -> Option<ObjectId> {
+    let trailer = data.get(data.len().checked_sub(hash_kind.len_in_bytes())?..)?;
+    let id = ObjectId::try_from(trailer).ok()?;
+    (!id.is_null()).then_some(id)
+}
+
+/// A directory with its own `.git`, directory or gitfile, that leads to a repository
+pub fn is_nested_repo(dir: &Path) -> bool {
+    gix::discover::is_git(&dir.join(".git")).is_ok()
+}
+
+/// Every file below the directory `rel_path` at `path`, as paths relative to
+/// the worktree, leaving out `.git`. Nested repositories are handed over
+/// whole, flagged, instead of walked.
+fn for_each_file(rel_path: &BStr, path: &Path, f: &mut impl FnMut(BString, bool)) -> Result<()> {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let Ok(entry) = entry else { continue };
+        let name = gix::path::os_string_into_bstring(entry.file_name())?;
+        if name == ".git" {
+            continue;
+        }
+        let mut child = rel_path.to_owned();
+        child.push(b'/');
+        child.extend_from_slice(&name);
+        let path = entry.path();
+        if !entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            f(child, false);
+        } else if is_nested_repo(&path) {
+            f(child, true);
+        } else {
+            for_each_file(child.as_bstr(), &path, f)?;
+        }
+    }
+    Ok(())
+}
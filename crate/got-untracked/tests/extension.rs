@@ -0,0 +1,160 @@
+//! The untracked cache extension as git writes and reads it
+
+use got_untracked::extension::{write_to_index, UntrackedCache};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+struct Repo {
+    dir: tempfile::TempDir,
+}
+
+impl Repo {
+    /// A repository with tracked files, and untracked ones spread over
+    /// `dirs` directories, whose index `git status` gave an untracked cache;
+    /// `config` applies to every git call, as `-c` does
+    fn with_cache(config: &[&str], dirs: usize) -> Self {
+        let repo = Repo {
+            dir: tempfile::tempdir().expect("temporary directory"),
+        };
+        repo.git(config, &["init", "-q"]);
+        let past = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        for name in ["tracked", "sub/tracked"] {
+            repo.write(name, past);
+        }
+        repo.git(config, &["add", "-A"]);
+        for n in 0..dirs {
+            repo.write(&format!("dir{n}/untracked"), past);
+        }
+        repo.write("untracked", past);
+        repo.status(config);
+        repo
+    }
+
+    fn write(&self, path: &str, mtime: std::time::SystemTime) {
+        let path = self.dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, "content\n").unwrap();
+        let file = std::fs::File::options().write(true).open(path).unwrap();
+        file.set_modified(mtime).unwrap();
+    }
+
+    /// Run git without the user's or the system's config; its stdout
+    fn git(&self, config: &[&str], args: &[&str]) -> String {
+        let mut command = Command::new("git");
+        for setting in config {
+            command.args(["-c", setting]);
+        }
+        let output = command
+            .args(args)
+            .current_dir(self.dir.path())
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_CONFIG_GLOBAL", "/dev/null")
+            .output()
+            .expect("git runs");
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).expect("UTF-8 output")
+    }
+
+    /// `git status --porcelain` through the untracked cache
+    fn status(&self, config: &[&str]) -> String {
+        let config = [config, &["core.untrackedCache=true"]].concat();
+        self.git(&config, &["status", "--porcelain"])
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir.path().join(".git/index")
+    }
+
+    fn index(&self) -> Vec<u8> {
+        std::fs::read(self.index_path()).unwrap()
+    }
+}
+
+/// The `UNTR` payload in index file `data`, after the last `UNTR` in it
+fn untracked_payload(data: &[u8]) -> &[u8] {
+    let at = data
+        .windows(4)
+        .rposition(|window| window == b"UNTR")
+        .expect("an untracked cache");
+    let len = u32::from_be_bytes(data[at + 4..at + 8].try_into().unwrap()) as usize;
+    &data[at + 8..at + 8 + len]
+}
+
+fn cache_of(path: &Path) -> UntrackedCache {
+    let data = std::fs::read(path).unwrap();
+    UntrackedCache::from_index(&data, gix_hash::Kind::Sha1).expect("a cache that parses")
+}
+
+#[test]
+fn git_written_cache_encodes_back_to_the_same_bytes() {
+    // past 64 directories the bitmaps take more than one word
+    for dirs in [3, 70] {
+        let repo = Repo::with_cache(&[], dirs);
+        let data = repo.index();
+        let cache = cache_of(&repo.index_path());
+        assert_eq!(cache.dirs[0].name, "");
+        assert!(cache.dirs[0].untracked.contains(&"untracked".into()));
+        assert_eq!(cache.encode(20), untracked_payload(&data), "{dirs} dirs");
+    }
+}
+
+/// The index layouts the cache is spliced into: version 4 entries, a split
+/// index, and the end-of-index-entry extension git checks extension offsets by
+#[test]
+fn git_reads_the_index_we_rewrite() {
+    let layouts: [&[&str]; 4] = [
+        &[],
+        &["index.version=4"],
+        &["core.splitIndex=true"],
+        &[
+            "index.recordEndOfIndexEntries=true",
+            "index.recordOffsetTable=true",
+            "index.threads=2",
+        ],
+    ];
+    for config in layouts {
+        let repo = Repo::with_cache(config, 3);
+        let original = repo.index();
+        let mut cache = cache_of(&repo.index_path());
+        cache.dirs[0].untracked.push("added-by-us".into());
+
+        let written = write_to_index(
+            &repo.index_path(),
+            &original,
+            &cache,
+            gix_hash::Kind::Sha1,
+            false,
+            0,
+        )
+        .unwrap();
+        assert!(written, "{config:?}");
+        let ours = repo.index();
+        assert_ne!(ours, original, "{config:?}");
+        assert_eq!(untracked_payload(&ours), cache.encode(20), "{config:?}");
+
+        // checksum, entries and the other extensions as git expects them: it
+        // writes the same bytes back, EOIE included
+        repo.git(config, &["fsck", "--no-dangling"]);
+        repo.git(config, &["update-index", "--force-write-index"]);
+        assert_eq!(repo.index(), ours, "{config:?}");
+        assert_eq!(
+            repo.git(config, &["ls-files", "--stage"]).lines().count(),
+            2,
+            "{config:?}"
+        );
+        // and walks with the cache; a new file makes it read the root again,
+        // which drops the name we added
+        repo.write("new", std::time::SystemTime::now());
+        let uncached = [config, &["core.untrackedCache=false"]].concat();
+        assert_eq!(
+            repo.status(config),
+            repo.git(&uncached, &["status", "--porcelain"]),
+            "{config:?}"
+        );
+    }
+}
@@ -61,6 +61,12 @@ pub enum Commands {
     /// Get shell hook script for GOT_D and GOT_F variables
     HookGot(HookGotArgs),
 
+    /// Watch the worktree and answer status queries, for got and git's fsmonitor
+    Daemon {
+        #[command(subcommand)]
+        command: DaemonArgs,
+    },
+
     /// Ignore files
     Nah {
         #[command(subcommand)]
@@ -78,10 +84,15 @@ pub struct GoldestArgs {
     #[arg(short = 'd', long, conflicts_with_all = ["file_only", "short", "porcelain"])]
     pub date_only: bool,
 
-    /// Filter equivalent to git status -u filtering
-    #[arg(short = 'u', value_name = "FILTER", num_args(0..=1))]
+    /// Filter equivalent to git status -u filtering; alone, the
+    /// status.showUntrackedFiles mode
+    #[arg(short = 'u', value_name = "FILTER", num_args(0..=1), default_missing_value = "")]
     pub untracked: Option<String>,
 
+    /// Include ignored files: traditional (the default), matching or no
+    #[arg(long, value_name = "MODE", num_args(0..=1), require_equals = true, default_missing_value = "traditional")]
+    pub ignored: Option<String>,
+
     /// Number of results to show
     #[arg(short = 'l', long, value_name = "LINES", default_value = "1")]
     pub lines: usize,
@@ -97,6 +108,38 @@ pub struct GoldestArgs {
     /// Show git status --porcelain output format
     #[arg(long, conflicts_with_all = ["file_only", "date_only", "short"])]
     pub porcelain: bool,
+
+    /// Compare against this revision instead of HEAD; `main...` for the merge base with main
+    #[arg(long, value_name = "REV")]
+    pub base: Option<String>,
+
+    /// Only consider unresolved merge conflicts
+    #[arg(long)]
+    pub unmerged: bool,
+
+    /// Terminate entries with NUL and print paths without quoting
+    #[arg(short = 'z')]
+    pub null_terminated: bool,
+
+    /// Include changes inside checked-out submodules
+    #[arg(long)]
+    pub recurse_submodules: bool,
+
+    /// Include changes inside repositories nested in the worktree
+    #[arg(long)]
+    pub recurse_nested: bool,
+
+    /// Report changes in every worktree of the repository, other checkouts with full paths
+    #[arg(long)]
+    pub all_worktrees: bool,
+
+    /// Hash every changed-looking file again instead of using .git/got/status-cache
+    #[arg(long)]
+    pub no_cache: bool,
+
+    /// Limit to paths matching these pathspecs
+    #[arg(value_name = "PATHSPEC")]
+    pub pathspec: Vec<std::ffi::OsString>,
 }
 
 #[derive(Args, Debug)]
@@ -149,6 +192,35 @@ pub enum ShellType {
     Zsh,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum DaemonArgs {
+    /// Watch the repository of the current directory until stopped
+    Run,
+
+    /// Print the daemon's status, like git status --porcelain
+    Status {
+        /// Terminate entries with NUL and print paths without quoting
+        #[arg(short = 'z')]
+        null_terminated: bool,
+    },
+
+    /// Stop the daemon of the current repository
+    Stop,
+
+    /// Answer git as a core.fsmonitor hook
+    Fsmonitor {
+        /// Hook protocol version; only 2 is spoken
+        version: u32,
+
+        /// Token from the last answer, empty on the first query
+        #[arg(default_value = "")]
+        token: String,
+    },
+
+    /// Make git status in the current repository ask the daemon through core.fsmonitor
+    Install,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum NahArgs {
     /// Add a file to ignore list
@@ -192,5 +264,9 @@ pub enum NahArgs {
         /// Add to global ignore list
         #[arg(short, long)]
         global: bool,
+
+        /// Limit to untracked files matching these pathspecs
+        #[arg(value_name = "PATHSPEC")]
+        pathspec: Vec<std::ffi::OsString>,
     },
 }
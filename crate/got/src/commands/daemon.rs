@@ -0,0 +1,89 @@
+use crate::cli::DaemonArgs;
+use anyhow::Result;
+
+#[cfg(target_os = "linux")]
+pub fn execute(args: DaemonArgs) -> Result<()> {
+    use crate::daemon;
+    use anyhow::Context;
+    use gixkit::{discover_repo, quote_non_ascii, quote_path, BStr};
+    use std::io::Write;
+
+    let repo = discover_repo(std::env::current_dir()?)?;
+    let socket = daemon::socket_path(&repo.git_dir().canonicalize()?);
+    let mut out = std::io::stdout().lock();
+
+    match args {
+        DaemonArgs::Run => daemon::run(),
+        DaemonArgs::Status { null_terminated } => {
+            let reply = daemon::request(&socket, "status")?;
+            if let Some(message) = reply.strip_prefix(b"error: ") {
+                anyhow::bail!("{}", String::from_utf8_lossy(message).trim_end());
+            }
+            if null_terminated {
+                out.write_all(&reply)?;
+                return Ok(());
+            }
+            // `XY path\0` entries, with the source of a rename or copy as
+            // another field, printed as `XY orig -> path`
+            let quote_non_ascii = quote_non_ascii(&repo);
+            let mut fields = reply.split(|&b| b == b'\0').filter(|f| !f.is_empty());
+            while let Some(entry) = fields.next() {
+                let (xy, path) = entry.split_at(3.min(entry.len()));
+                out.write_all(xy)?;
+                if xy.iter().any(|&c| c == b'R' || c == b'C') {
+                    if let Some(orig) = fields.next() {
+                        out.write_all(&quote_path(BStr::new(orig), quote_non_ascii))?;
+                        out.write_all(b" -> ")?;
+                    }
+                }
+                out.write_all(&quote_path(BStr::new(path), quote_non_ascii))?;
+                out.write_all(b"\n")?;
+            }
+            Ok(())
+        }
+        DaemonArgs::Stop => {
+            daemon::request(&socket, "stop")?;
+            println!("Stopped got daemon");
+            Ok(())
+        }
+        DaemonArgs::Fsmonitor { version, token } => {
+            if version != 2 {
+                anyhow::bail!(
+                    "Unsupported fsmonitor hook version {}; set core.fsmonitorHookVersion to 2",
+                    version
+                );
+            }
+            // without a daemon, an unknown token and `/` have git check everything
+            let reply = daemon::request(&socket, &format!("fsmonitor {}", token))
+                .ok()
+                .filter(|reply| reply.starts_with(b"got:"))
+                .unwrap_or_else(|| b"got:0:0\0/\0".to_vec());
+            out.write_all(&reply)?;
+            Ok(())
+        }
+        DaemonArgs::Install => {
+            for (key, value) in [
+                ("core.fsmonitor", "got daemon fsmonitor"),
+                ("core.fsmonitorHookVersion", "2"),
+            ] {
+                let output = std::process::Command::new("git")
+                    .args(["config", key, value])
+                    .output()
+                    .context("Failed to execute git config command")?;
+                if !output.status.success() {
+                    anyhow::bail!(
+                        "git config failed: {}",
+                        String::from_utf8_lossy(&output.stderr)
+                    );
+                }
+            }
+            println!("git status now asks got daemon; start it with `got daemon run`");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn execute(_args: DaemonArgs) -> Result<()> {
+    anyhow::bail!("got daemon watches the worktree with inotify, which is Linux only")
+}
@@ -9,6 +9,7 @@ pub fn execute(command: Commands) -> Result<()> {
         Commands::Statusd(args) => statusd::execute(args),
         Commands::Commitd(args) => commitd::execute(args),
         Commands::HookGot(args) => hook_got::execute(args),
+        Commands::Daemon { command: args } => daemon::execute(args),
         Commands::Nah { command: args } => nah::execute(args),
     }
 }
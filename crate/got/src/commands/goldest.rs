@@ -1,25 +1,111 @@
 use crate::cli::GoldestArgs;
 use anyhow::Result;
-use gixkit::{open_repo, IterMode, RepoIterBuilder};
+use gixkit::{
+    configured_untracked, cwd_prefix, discover_repo, list_worktrees, quote_path, relative_path,
+    BStr, BString, FileStatus, IgnoredFilter, IterMode, RepoIterBuilder, StatusChar,
+    UntrackedFilter,
+};
+use std::io::Write;
 use std::sync::Arc;
 
 pub fn execute(args: GoldestArgs) -> Result<()> {
-    let repo = open_repo(std::env::current_dir()?)?;
+    let repo = discover_repo(std::env::current_dir()?)?;
+    #[allow(clippy::arc_with_non_send_sync)]
     let repo = Arc::new(repo);
 
-    let show_untracked = args.untracked.is_some();
-    let mode = if show_untracked {
+    let ignored = match args.ignored.as_deref() {
+        Some(value) => IgnoredFilter::from_arg(value)
+            .ok_or_else(|| anyhow::anyhow!("Invalid ignored mode '{}'", value))?,
+        None => IgnoredFilter::No,
+    };
+    // -u alone takes the status.showUntrackedFiles mode, the one the untracked
+    // cache is kept for, or normal where that turns them off; --ignored on its
+    // own walks the worktree as -unormal would but keeps only the ignored paths
+    let untracked = match args.untracked.as_deref() {
+        Some("") => match configured_untracked(&repo) {
+            UntrackedFilter::No => UntrackedFilter::Normal,
+            configured => configured,
+        },
+        Some(value) => UntrackedFilter::from_config(value)
+            .ok_or_else(|| anyhow::anyhow!("Invalid untracked files mode '{}'", value))?,
+        None if ignored != IgnoredFilter::No => UntrackedFilter::Normal,
+        None => UntrackedFilter::No,
+    };
+    let mode = if untracked != UntrackedFilter::No {
         IterMode::Both
     } else {
         IterMode::Tracked
     };
 
-    let repo_iter = RepoIterBuilder::new(Arc::clone(&repo))
-        .mode(mode)
-        .include_metadata(true)
-        .build()?;
+    let status = |repo| -> gixkit::Result<Vec<FileStatus>> {
+        let builder = RepoIterBuilder::new(repo)
+            .mode(mode)
+            .untracked(untracked)
+            .ignored(ignored)
+            .include_metadata(true)
+            .recurse_submodules(args.recurse_submodules)
+            .recurse_nested_repos(args.recurse_nested)
+            .status_cache(!args.no_cache)
+            .parallel(0)
+            .pathspec(args.pathspec.iter().map(|p| p.as_encoded_bytes()));
+        let builder = match args.base {
+            Some(ref rev) => builder.base(rev),
+            None => builder,
+        };
+        builder.build()?.collect()
+    };
+
+    // relative to the current directory, except for the machine-readable
+    // formats, which keep repository paths as `git status --porcelain` does
+    let prefix = if args.null_terminated || args.porcelain {
+        None
+    } else {
+        cwd_prefix(&repo)
+    };
+    let mut files = Vec::new();
+    let current = repo.workdir().and_then(|dir| dir.canonicalize().ok());
+    let worktrees = if args.all_worktrees {
+        list_worktrees(&repo)?
+    } else {
+        Vec::new()
+    };
+    if current.is_some() {
+        let mut statuses = status(Arc::clone(&repo))?;
+        if let Some(ref prefix) = prefix {
+            for file in &mut statuses {
+                file.map_paths(|path| relative_path(path, prefix.as_ref()));
+            }
+        }
+        files.extend(statuses);
+    } else if !args.all_worktrees {
+        anyhow::bail!("Repository has no working directory; try --all-worktrees");
+    }
+    // other checkouts of the same repository, by their full path
+    for worktree in worktrees {
+        if worktree.prunable || worktree.path.canonicalize().ok() == current {
+            continue;
+        }
+        #[allow(clippy::arc_with_non_send_sync)]
+        let mut statuses = status(Arc::new(worktree.open()?))?;
+        let mut root = BString::from(worktree.path.as_os_str().as_encoded_bytes());
+        root.push(b'/');
+        for file in &mut statuses {
+            file.map_paths(|path| {
+                let mut full = root.clone();
+                full.extend_from_slice(path);
+                full
+            });
+        }
+        files.extend(statuses);
+    }
+
+    if args.untracked.is_none() {
+        files.retain(|f| f.worktree_status != StatusChar::Untracked);
+    }
 
-    let mut files: Vec<_> = repo_iter.collect::<Result<Vec<_>>>()?;
+    if args.unmerged {
+        files.retain(|f| f.is_conflicted());
+    }
 
     files.sort_by_key(|f| {
         f.metadata
@@ -32,6 +118,18 @@ pub fn execute(args: GoldestArgs) -> Result<()> {
     let lines = args.lines;
     let files = files.into_iter().skip(skip).take(lines).collect::<Vec<_>>();
 
+    // -z prints paths verbatim and ends each entry with NUL, like `git status -z`
+    let quote_non_ascii = gixkit::quote_non_ascii(&repo);
+    let path_bytes = |path: &BStr| -> Vec<u8> {
+        if args.null_terminated {
+            path.to_vec()
+        } else {
+            quote_path(path, quote_non_ascii).into()
+        }
+    };
+    let terminator = if args.null_terminated { b'\0' } else { b'\n' };
+    let mut out = std::io::stdout().lock();
+
     for file in files {
         let metadata = file
             .metadata
@@ -41,27 +139,33 @@ pub fn execute(args: GoldestArgs) -> Result<()> {
             .format("%m-%d-%yT%H:%M:%SZ")
             .to_string();
 
+        let mut line = Vec::new();
         if args.file_only {
-            println!("{}", file.path);
+            line.extend(path_bytes(file.path.as_ref()));
         } else if args.date_only {
-            println!("{}", modified_time);
-        } else if args.short {
-            let index_char: char = file.index_status.into();
-            let worktree_char: char = file.worktree_status.into();
-            println!(
-                "{}{} {} {}",
-                index_char, worktree_char, modified_time, file.path
-            );
-        } else if args.porcelain {
-            let index_char: char = file.index_status.into();
-            let worktree_char: char = file.worktree_status.into();
-            println!(
-                "{}{} {} {} {}",
-                index_char, worktree_char, modified_time, file.path, metadata.size
-            );
+            line.extend(modified_time.bytes());
+        } else if args.short || args.porcelain {
+            let (x, y) = file.xy();
+            line.extend(format!("{}{} {} ", char::from(x), char::from(y), modified_time).bytes());
+            if args.null_terminated {
+                line.extend(path_bytes(file.path.as_ref()));
+            } else {
+                line.extend(file.display_path(quote_non_ascii).iter());
+            }
+            if args.porcelain {
+                line.extend(format!(" {}", metadata.size).bytes());
+            }
+            // the rename source follows as its own NUL-terminated field
+            if let (true, Some(orig_path)) = (args.null_terminated, &file.orig_path) {
+                line.push(terminator);
+                line.extend(path_bytes(orig_path.as_ref()));
+            }
         } else {
-            println!("{} {}", file.path, modified_time);
+            line.extend(path_bytes(file.path.as_ref()));
+            line.extend(format!(" {}", modified_time).bytes());
         }
+        line.push(terminator);
+        out.write_all(&line)?;
     }
 
     Ok(())
@@ -1,4 +1,5 @@
 pub mod commitd;
+pub mod daemon;
 pub mod dispatch;
 pub mod goldest;
 pub mod gotsel;
@@ -0,0 +1,169 @@
+//! Just enough inotify for watching a worktree: recursive directory watches
+//! and decoded events, with a timeout for debouncing
+
+use std::collections::HashMap;
+use std::ffi::{CString, OsStr};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const MASK: u32 = libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_MODIFY
+    | libc::IN_ATTRIB
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_DELETE_SELF
+    | libc::IN_MOVE_SELF
+    | libc::IN_ONLYDIR;
+
+pub enum Event {
+    /// Something at `path` was created, removed, written, or moved in or out
+    Changed {
+        path: PathBuf,
+        is_dir: bool,
+        /// A directory appeared, which needs watches of its own
+        new_dir: bool,
+    },
+    /// The kernel queue overflowed and events were lost
+    Overflow,
+}
+
+pub struct Inotify {
+    fd: OwnedFd,
+    watches: HashMap<i32, PathBuf>,
+    buf: Vec<u8>,
+}
+
+impl Inotify {
+    pub fn new() -> io::Result<Self> {
+        // SAFETY: plain syscall; the descriptor is owned from here on
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            // SAFETY: `fd` is a fresh descriptor nobody else owns
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            watches: HashMap::new(),
+            buf: vec![0; 64 * 1024],
+        })
+    }
+
+    /// Watch `dir` itself, not its subdirectories
+    pub fn watch(&mut self, dir: &Path) -> io::Result<()> {
+        let path = CString::new(dir.as_os_str().as_bytes())?;
+        // SAFETY: `path` is a valid NUL-terminated string for the whole call
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), MASK) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.watches.insert(wd, dir.to_path_buf());
+        Ok(())
+    }
+
+    /// Watch `dir` and every directory below it except `.git`, returning the
+    /// paths found inside, which may have appeared before their watch did
+    pub fn watch_tree(&mut self, dir: &Path) -> io::Result<Vec<(PathBuf, bool)>> {
+        let mut found = Vec::new();
+        let mut stack = vec![dir.to_path_buf()];
+        while let Some(dir) = stack.pop() {
+            if let Err(e) = self.watch(&dir) {
+                // gone again already, or not a directory after all
+                if e.raw_os_error() == Some(libc::ENOSPC) {
+                    return Err(e);
+                }
+                continue;
+            }
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                if entry.file_name() == ".git" {
+                    continue;
+                }
+                let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+                if is_dir {
+                    stack.push(entry.path());
+                }
+                found.push((entry.path(), is_dir));
+            }
+        }
+        Ok(found)
+    }
+
+    /// Events as they arrive, waiting up to `timeout` (forever for `None`);
+    /// empty when it passed quietly
+    pub fn read(&mut self, timeout: Option<Duration>) -> io::Result<Vec<Event>> {
+        let mut pollfd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let timeout = timeout.map_or(-1, |t| t.as_millis().min(i32::MAX as u128) as i32);
+        // SAFETY: one valid pollfd
+        let ready = unsafe { libc::poll(&mut pollfd, 1, timeout) };
+        if ready < 0 {
+            let e = io::Error::last_os_error();
+            return if e.kind() == io::ErrorKind::Interrupted {
+                Ok(Vec::new())
+            } else {
+                Err(e)
+            };
+        }
+        if ready == 0 {
+            return Ok(Vec::new());
+        }
+
+        // SAFETY: reading into our own buffer, at most its length
+        let len = unsafe {
+            libc::read(
+                self.fd.as_raw_fd(),
+                self.buf.as_mut_ptr().cast(),
+                self.buf.len(),
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let header = std::mem::size_of::<libc::inotify_event>();
+        let mut events = Vec::new();
+        let mut offset = 0;
+        while offset + header <= len as usize {
+            // SAFETY: the kernel wrote a whole event header here; it may be unaligned
+            let event: libc::inotify_event = unsafe {
+                std::ptr::read_unaligned(self.buf[offset..].as_ptr().cast::<libc::inotify_event>())
+            };
+            let name = &self.buf[offset + header..offset + header + event.len as usize];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+            offset += header + event.len as usize;
+
+            if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                events.push(Event::Overflow);
+                continue;
+            }
+            if event.mask & libc::IN_IGNORED != 0 {
+                self.watches.remove(&event.wd);
+                continue;
+            }
+            let Some(dir) = self.watches.get(&event.wd) else {
+                continue;
+            };
+            let path = if name.is_empty() {
+                dir.clone()
+            } else {
+                dir.join(OsStr::from_bytes(name))
+            };
+            let is_dir = event.mask & libc::IN_ISDIR != 0;
+            events.push(Event::Changed {
+                path,
+                is_dir,
+                new_dir: is_dir && event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0,
+            });
+        }
+        Ok(events)
+    }
+}
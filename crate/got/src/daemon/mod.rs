@@ -0,0 +1,452 @@
+//! `got daemon`: a long-running status model for one worktree
+//!
+//! A watcher thread follows the worktree with inotify, logging every changed
+//! path with a sequence number and rescanning with [`RepoIterBuilder`] once
+//! things settle. Clients talk to it over `.git/got/daemon.sock`, one request
+//! line per connection:
+//!
+//! - `status`: every changed path, as `git status --porcelain -z` prints them
+//! - `fsmonitor <token>`: a git fsmonitor v2 hook reply, a new token and the
+//!   paths changed since `token`, all NUL-terminated
+//! - `stop`: shut the daemon down
+//!
+//! Before answering, the daemon writes a cookie file into `.git/got` and waits
+//! for its own watcher to report it, so every change made before the request
+//! is accounted for.
+
+mod inotify;
+
+use anyhow::{Context, Result};
+use gixkit::{BStr, BString, FileStatus, IterMode, RepoIterBuilder, ThreadSafeRepository};
+use inotify::{Event, Inotify};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long the worktree has to stay quiet before the watcher rescans
+const SETTLE: Duration = Duration::from_millis(100);
+
+/// How long a request waits for the watcher to catch up with its cookie
+const COOKIE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Changed paths kept for fsmonitor queries; older tokens get a full rescan
+const MAX_CHANGES: usize = 100_000;
+
+/// The fsmonitor reply that makes git check everything itself
+const TRIVIAL: &[u8] = b"/\0";
+
+/// Where the daemon of the repository at `git_dir` listens
+pub fn socket_path(git_dir: &Path) -> PathBuf {
+    git_dir.join("got").join("daemon.sock")
+}
+
+/// Send one request to the daemon listening at `socket` and return its reply
+pub fn request(socket: &Path, line: &str) -> Result<Vec<u8>> {
+    let mut stream = UnixStream::connect(socket)
+        .with_context(|| format!("No got daemon listening on '{}'", socket.display()))?;
+    stream.write_all(line.as_bytes())?;
+    stream.write_all(b"\n")?;
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply)?;
+    Ok(reply)
+}
+
+/// Where the daemon's own paths live, fixed at startup
+struct Paths {
+    work_dir: PathBuf,
+    git_dir: PathBuf,
+    common_dir: PathBuf,
+    /// `git_dir/got`, for the socket, cookies and the status cache
+    got_dir: PathBuf,
+}
+
+/// Every worktree change with a sequence number, for answering fsmonitor
+/// tokens; past `capacity` changes the older half is dropped
+pub struct ChangeLog {
+    /// `(seq, path)` for each change, directories ending in `/`
+    changes: Vec<(u64, BString)>,
+    seq: u64,
+    /// Changes from this one on are all logged; earlier ones were missed (an
+    /// overflow) or trimmed
+    start: u64,
+    capacity: usize,
+}
+
+impl ChangeLog {
+    pub fn new(capacity: usize) -> Self {
+        ChangeLog {
+            changes: Vec::new(),
+            seq: 0,
+            start: 1,
+            capacity,
+        }
+    }
+
+    /// Sequence number of the latest change
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    pub fn record(&mut self, path: BString) {
+        self.seq += 1;
+        self.changes.push((self.seq, path));
+        if self.changes.len() > self.capacity {
+            let drop = self.changes.len() - self.capacity / 2;
+            self.start = self.changes[drop].0;
+            self.changes.drain(..drop);
+        }
+    }
+
+    /// Anything may have changed unseen: no earlier token can be answered
+    pub fn forget(&mut self) {
+        self.seq += 1;
+        self.start = self.seq + 1;
+        self.changes.clear();
+    }
+
+    /// Every path changed after change `seq`, each once, or `None` when the log
+    /// doesn't reach back that far
+    pub fn since(&self, seq: u64) -> Option<Vec<&BStr>> {
+        if seq + 1 < self.start || seq > self.seq {
+            return None;
+        }
+        let start = self.changes.partition_point(|(s, _)| *s <= seq);
+        let mut seen = std::collections::HashSet::new();
+        let paths = self.changes[start..]
+            .iter()
+            .map(|(_, path)| path.as_ref())
+            .filter(|path| seen.insert(*path))
+            .collect();
+        Some(paths)
+    }
+}
+
+struct State {
+    /// The last full scan
+    statuses: Vec<FileStatus>,
+    changes: ChangeLog,
+    /// Something changed since the last scan
+    dirty: bool,
+    /// Highest cookie the watcher has seen
+    cookie_seen: u64,
+}
+
+struct Shared {
+    /// The repository as discovered at startup, `GIT_DIR` and `GIT_WORK_TREE`
+    /// included; its configuration is read once, like the paths
+    repo: ThreadSafeRepository,
+    paths: Paths,
+    state: Mutex<State>,
+    changed: Condvar,
+    /// Held for a whole scan, so the watcher and requests don't scan twice
+    scanning: Mutex<()>,
+    cookies: AtomicU64,
+    /// Tells tokens of this daemon from those of an earlier one
+    instance: String,
+}
+
+/// Run the daemon for the repository found from the current directory until
+/// a client asks it to stop
+pub fn run() -> Result<()> {
+    let repo = gixkit::discover_repo(std::env::current_dir()?)?;
+    let work_dir = repo
+        .workdir()
+        .ok_or_else(|| anyhow::anyhow!("Repository has no working directory to watch"))?
+        .canonicalize()?;
+    let git_dir = repo.git_dir().canonicalize()?;
+    let common_dir = repo.common_dir().canonicalize()?;
+    let got_dir = git_dir.join("got");
+    std::fs::create_dir_all(&got_dir)?;
+
+    let socket = socket_path(&git_dir);
+    if socket.exists() {
+        if UnixStream::connect(&socket).is_ok() {
+            anyhow::bail!(
+                "A got daemon is already running for '{}'",
+                work_dir.display()
+            );
+        }
+        // left behind by a daemon that didn't get to clean up
+        std::fs::remove_file(&socket)?;
+    }
+    let listener = UnixListener::bind(&socket)
+        .with_context(|| format!("Failed to listen on '{}'", socket.display()))?;
+
+    let started = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let shared = Arc::new(Shared {
+        repo: repo.into_sync(),
+        paths: Paths {
+            work_dir,
+            git_dir,
+            common_dir,
+            got_dir,
+        },
+        state: Mutex::new(State {
+            statuses: Vec::new(),
+            changes: ChangeLog::new(MAX_CHANGES),
+            dirty: true,
+            cookie_seen: 0,
+        }),
+        changed: Condvar::new(),
+        scanning: Mutex::new(()),
+        cookies: AtomicU64::new(0),
+        instance: format!("{}-{}", started.as_nanos(), std::process::id()),
+    });
+
+    // watches go up before the first scan, so nothing falls in between
+    let mut inotify = Inotify::new()?;
+    watch_all(&mut inotify, &shared.paths)?;
+    refresh(&shared)?;
+    let watcher = Arc::clone(&shared);
+    std::thread::spawn(move || {
+        if let Err(e) = watch(inotify, &watcher) {
+            eprintln!("got daemon: watcher stopped: {:#}", e);
+            std::process::exit(1);
+        }
+    });
+
+    eprintln!("got daemon: watching '{}'", shared.paths.work_dir.display());
+    let result = serve(&listener, &shared);
+    let _ = std::fs::remove_file(&socket);
+    result
+}
+
+fn serve(listener: &UnixListener, shared: &Shared) -> Result<()> {
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("got daemon: {}", e);
+                continue;
+            }
+        };
+        let mut line = String::new();
+        if BufReader::new(&stream).read_line(&mut line).is_err() {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let reply = match (words.next(), words.next()) {
+            (Some("status"), None) => status_reply(shared),
+            (Some("fsmonitor"), token) => fsmonitor_reply(shared, token.unwrap_or_default()),
+            (Some("stop"), None) => {
+                let _ = stream.write_all(b"ok\n");
+                return Ok(());
+            }
+            _ => Err(anyhow::anyhow!("Unknown request '{}'", line.trim_end())),
+        };
+        // a client that went away doesn't concern the daemon
+        let _ = match reply {
+            Ok(reply) => stream.write_all(&reply),
+            Err(e) => {
+                eprintln!("got daemon: {:#}", e);
+                stream.write_all(format!("error: {:#}\n", e).as_bytes())
+            }
+        };
+    }
+    Ok(())
+}
+
+/// `XY path\0`, followed by `orig\0` for renames and copies
+fn status_reply(shared: &Shared) -> Result<Vec<u8>> {
+    sync_cookie(shared)?;
+    refresh(shared)?;
+    let state = shared.state.lock().unwrap();
+    let mut reply = Vec::new();
+    for status in &state.statuses {
+        let (x, y) = status.xy();
+        reply.extend(format!("{}{} ", char::from(x), char::from(y)).bytes());
+        reply.extend_from_slice(&status.path);
+        reply.push(0);
+        if let Some(ref orig_path) = status.orig_path {
+            reply.extend_from_slice(orig_path);
+            reply.push(0);
+        }
+    }
+    Ok(reply)
+}
+
+/// The new token, then every path changed since `token`, or `/` when the
+/// token isn't one this daemon can answer for
+fn fsmonitor_reply(shared: &Shared, token: &str) -> Result<Vec<u8>> {
+    sync_cookie(shared)?;
+    let state = shared.state.lock().unwrap();
+    let mut reply = format!("got:{}:{}\0", shared.instance, state.changes.seq()).into_bytes();
+    let paths = token
+        .strip_prefix("got:")
+        .and_then(|rest| rest.rsplit_once(':'))
+        .filter(|(instance, _)| *instance == shared.instance)
+        .and_then(|(_, seq)| seq.parse::<u64>().ok())
+        .and_then(|seq| state.changes.since(seq));
+    let Some(paths) = paths else {
+        reply.extend_from_slice(TRIVIAL);
+        return Ok(reply);
+    };
+    for path in paths {
+        reply.extend_from_slice(path);
+        reply.push(0);
+    }
+    Ok(reply)
+}
+
+/// Wait until the watcher has caught up with everything that happened before now
+fn sync_cookie(shared: &Shared) -> Result<()> {
+    let cookie = shared.cookies.fetch_add(1, Ordering::Relaxed) + 1;
+    let path = shared.paths.got_dir.join(format!("cookie-{}", cookie));
+    std::fs::write(&path, b"")?;
+    let deadline = Instant::now() + COOKIE_TIMEOUT;
+    let mut state = shared.state.lock().unwrap();
+    while state.cookie_seen < cookie {
+        let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+            break;
+        };
+        state = shared.changed.wait_timeout(state, left).unwrap().0;
+    }
+    let caught_up = state.cookie_seen >= cookie;
+    drop(state);
+    let _ = std::fs::remove_file(&path);
+    if !caught_up {
+        anyhow::bail!("The watcher didn't catch up within {:?}", COOKIE_TIMEOUT);
+    }
+    Ok(())
+}
+
+/// Rescan the worktree if anything changed since the last scan
+fn refresh(shared: &Shared) -> Result<()> {
+    let _scanning = shared.scanning.lock().unwrap();
+    {
+        let mut state = shared.state.lock().unwrap();
+        if !state.dirty {
+            return Ok(());
+        }
+        // changes from here on make the next refresh scan again
+        state.dirty = false;
+    }
+    let scan = || -> gixkit::Result<Vec<FileStatus>> {
+        #[allow(clippy::arc_with_non_send_sync)]
+        let repo = Arc::new(shared.repo.to_thread_local());
+        RepoIterBuilder::new(repo)
+            .mode(IterMode::Both)
+            .status_cache(true)
+            // writing the untracked cache would wake the watcher on the index
+            .write_untracked_cache(false)
+            .parallel(0)
+            .build()?
+            .collect()
+    };
+    match scan() {
+        Ok(statuses) => {
+            shared.state.lock().unwrap().statuses = statuses;
+            Ok(())
+        }
+        Err(e) => {
+            shared.state.lock().unwrap().dirty = true;
+            Err(e.into())
+        }
+    }
+}
+
+/// The worktree, recursively, plus what in the git dir can change the status:
+/// the index and HEAD, the refs, and `got/` for cookies
+fn watch_all(inotify: &mut Inotify, paths: &Paths) -> Result<()> {
+    inotify.watch_tree(&paths.work_dir)?;
+    inotify.watch(&paths.git_dir)?;
+    inotify.watch(&paths.got_dir)?;
+    if paths.common_dir != paths.git_dir {
+        inotify.watch(&paths.common_dir)?;
+    }
+    inotify.watch_tree(&paths.common_dir.join("refs"))?;
+    Ok(())
+}
+
+fn watch(mut inotify: Inotify, shared: &Shared) -> Result<()> {
+    loop {
+        let events = inotify.read(Some(SETTLE))?;
+        if events.is_empty() {
+            if let Err(e) = refresh(shared) {
+                eprintln!("got daemon: {:#}", e);
+            }
+            // nothing more to do until the next event
+            for event in inotify.read(None)? {
+                handle(&mut inotify, shared, event)?;
+            }
+            continue;
+        }
+        for event in events {
+            handle(&mut inotify, shared, event)?;
+        }
+    }
+}
+
+fn handle(inotify: &mut Inotify, shared: &Shared, event: Event) -> Result<()> {
+    let paths = &shared.paths;
+    let (path, is_dir, new_dir) = match event {
+        Event::Overflow => {
+            // anything may have happened: forget the log and start over
+            *inotify = Inotify::new()?;
+            watch_all(inotify, paths)?;
+            let mut state = shared.state.lock().unwrap();
+            state.changes.forget();
+            state.dirty = true;
+            return Ok(());
+        }
+        Event::Changed {
+            path,
+            is_dir,
+            new_dir,
+        } => (path, is_dir, new_dir),
+    };
+
+    if let Ok(rest) = path.strip_prefix(&paths.got_dir) {
+        let name = rest.as_os_str().to_string_lossy();
+        if let Some(cookie) = name.strip_prefix("cookie-").and_then(|n| n.parse().ok()) {
+            let mut state = shared.state.lock().unwrap();
+            state.cookie_seen = state.cookie_seen.max(cookie);
+            shared.changed.notify_all();
+        }
+        // the status cache and the socket are the daemon's own doing
+        return Ok(());
+    }
+    if path.starts_with(&paths.git_dir) || path.starts_with(&paths.common_dir) {
+        // lock files come and go around every write of the file they guard
+        if path.extension().is_some_and(|ext| ext == "lock") {
+            return Ok(());
+        }
+        if new_dir {
+            inotify.watch_tree(&path)?;
+        }
+        shared.state.lock().unwrap().dirty = true;
+        return Ok(());
+    }
+    let Ok(rel) = path.strip_prefix(&paths.work_dir) else {
+        return Ok(());
+    };
+    if rel.as_os_str().is_empty() {
+        return Ok(());
+    }
+
+    let mut changed = vec![(rel.to_path_buf(), is_dir)];
+    if new_dir {
+        // whatever was created inside before the watch was in place
+        for (inner, is_dir) in inotify.watch_tree(&path)? {
+            if let Ok(rel) = inner.strip_prefix(&paths.work_dir) {
+                changed.push((rel.to_path_buf(), is_dir));
+            }
+        }
+    }
+    let mut state = shared.state.lock().unwrap();
+    for (rel, is_dir) in changed {
+        let mut rel = BString::from(rel.as_os_str().as_bytes());
+        if is_dir {
+            rel.push(b'/');
+        }
+        state.changes.record(rel);
+    }
+    state.dirty = true;
+    Ok(())
+}
@@ -1,4 +1,6 @@
 pub mod cli;
 pub mod commands;
+#[cfg(target_os = "linux")]
+pub mod daemon;
 
 pub use cli::*;
@@ -0,0 +1,222 @@
+//! `got daemon`: its socket protocol, fsmonitor tokens and change log
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use common::Repo;
+use gixkit::BString;
+use got::daemon::{request, socket_path, ChangeLog};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// `got daemon run` in the background, stopped when dropped
+struct Daemon {
+    child: Child,
+    socket: PathBuf,
+}
+
+impl Daemon {
+    /// Start it in `dir` with `envs`, and wait until it listens at the socket
+    /// of `git_dir`
+    fn start(dir: &Path, git_dir: &Path, envs: &[(&str, &Path)]) -> Self {
+        let child = Command::new(assert_cmd::cargo::cargo_bin!("got"))
+            .args(["daemon", "run"])
+            .current_dir(dir)
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_CONFIG_GLOBAL", "/dev/null")
+            .envs(envs.iter().copied())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("got daemon runs");
+        let mut daemon = Daemon {
+            child,
+            socket: socket_path(&git_dir.canonicalize().unwrap()),
+        };
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while request(&daemon.socket, "status").is_err() {
+            if let Some(status) = daemon.child.try_wait().unwrap() {
+                panic!("got daemon exited early: {status}");
+            }
+            assert!(Instant::now() < deadline, "got daemon isn't listening");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        daemon
+    }
+
+    fn request(&self, line: &str) -> Vec<u8> {
+        request(&self.socket, line).expect("a reply")
+    }
+
+    /// The reply to `fsmonitor <token>`: the new token, and the paths
+    fn fsmonitor(&self, token: &str) -> (String, Vec<String>) {
+        let reply = self.request(&format!("fsmonitor {token}"));
+        let mut fields = reply
+            .split(|&b| b == 0)
+            .map(|field| String::from_utf8(field.to_vec()).unwrap());
+        let token = fields.next().unwrap();
+        let mut paths: Vec<String> = fields.filter(|field| !field.is_empty()).collect();
+        paths.sort();
+        (token, paths)
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        if request(&self.socket, "stop").is_err() {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+fn changed_repo() -> Repo {
+    let repo = Repo::init();
+    repo.write("tracked", "1\n");
+    repo.commit_all("initial");
+    repo.write("tracked", "2\n");
+    repo.write("untracked", "?\n");
+    repo
+}
+
+#[test]
+fn status_replies_as_git_status_porcelain_z() {
+    let repo = changed_repo();
+    let daemon = Daemon::start(repo.path(), &repo.file(".git"), &[]);
+    assert_eq!(
+        daemon.request("status"),
+        repo.git(&["status", "--porcelain", "-z"]).as_bytes()
+    );
+
+    // changes made before a request are in its reply
+    repo.write("another", "?\n");
+    assert_eq!(
+        daemon.request("status"),
+        repo.git(&["status", "--porcelain", "-z"]).as_bytes()
+    );
+}
+
+#[test]
+fn unknown_requests_get_an_error_and_stop_ends_the_daemon() {
+    let repo = changed_repo();
+    let mut daemon = Daemon::start(repo.path(), &repo.file(".git"), &[]);
+    assert_eq!(
+        daemon.request("frobnicate"),
+        b"error: Unknown request 'frobnicate'\n"
+    );
+    assert_eq!(daemon.request("stop"), b"ok\n");
+    assert!(daemon.child.wait().unwrap().success());
+    assert!(!daemon.socket.exists());
+}
+
+#[test]
+fn fsmonitor_tokens() {
+    let repo = changed_repo();
+    let daemon = Daemon::start(repo.path(), &repo.file(".git"), &[]);
+
+    // a token from no daemon or another one has git check everything
+    for token in ["", "builtin:0", "got:other-instance:0"] {
+        let (new_token, paths) = daemon.fsmonitor(token);
+        assert!(new_token.starts_with("got:"), "{token}");
+        assert_eq!(paths, ["/"], "{token}");
+    }
+
+    let (token, _) = daemon.fsmonitor("");
+    repo.write("dir/new", "?\n");
+    repo.write("tracked", "3\n");
+    let (next, paths) = daemon.fsmonitor(&token);
+    assert_ne!(next, token);
+    assert!(paths.contains(&"dir/new".to_owned()), "{paths:?}");
+    assert!(paths.contains(&"tracked".to_owned()), "{paths:?}");
+
+    // nothing since the latest token; one from the future is unknown
+    assert_eq!(daemon.fsmonitor(&next).1, Vec::<String>::new());
+    let (instance, seq) = next.rsplit_once(':').unwrap();
+    let future = format!("{instance}:{}", seq.parse::<u64>().unwrap() + 1);
+    assert_eq!(daemon.fsmonitor(&future).1, ["/"]);
+}
+
+/// Every scan opens the repository the daemon discovered, not the one a plain
+/// open of the worktree would find
+#[test]
+fn scans_keep_git_dir_and_work_tree_from_the_environment() {
+    let repo = changed_repo();
+    let git_dir = tempfile::tempdir().unwrap();
+    let git_dir = git_dir.path().join("repo.git");
+    std::fs::rename(repo.file(".git"), &git_dir).unwrap();
+    let envs = [
+        ("GIT_DIR", git_dir.as_path()),
+        ("GIT_WORK_TREE", repo.path()),
+    ];
+    let daemon = Daemon::start(repo.path(), &git_dir, &envs);
+
+    repo.write("another", "?\n");
+    let status = daemon.request("status");
+    let status: Vec<&[u8]> = status.split(|&b| b == 0).collect();
+    assert_eq!(
+        status,
+        [&b" M tracked"[..], b"?? another", b"?? untracked", b""]
+    );
+}
+
+/// A scan leaves the index alone: writing the untracked cache into it would
+/// be an index event, and so another scan, and another write
+#[test]
+fn idle_repository_settles_after_one_scan() {
+    let repo = changed_repo();
+    repo.git(&["config", "core.untrackedCache", "true"]);
+    repo.write("dir/untracked", "?\n");
+    let index = repo.file(".git/index");
+    let before = (
+        std::fs::metadata(&index).unwrap().ino(),
+        std::fs::read(&index).unwrap(),
+    );
+
+    let daemon = Daemon::start(repo.path(), &repo.file(".git"), &[]);
+    let status = daemon.request("status");
+    std::thread::sleep(Duration::from_millis(200));
+    let after = (
+        std::fs::metadata(&index).unwrap().ino(),
+        std::fs::read(&index).unwrap(),
+    );
+    assert!(after == before, "the daemon wrote the index");
+    // git status writes the untracked cache itself, so it goes last
+    assert_eq!(
+        status,
+        repo.git(&["status", "--porcelain", "-z"]).as_bytes()
+    );
+}
+
+fn paths(log: &ChangeLog, seq: u64) -> Option<Vec<&str>> {
+    let paths = log.since(seq)?;
+    Some(
+        paths
+            .into_iter()
+            .map(|path| std::str::from_utf8(path).unwrap())
+            .collect(),
+    )
+}
+
+#[test]
+fn change_log_keeps_the_newer_half_past_its_capacity() {
+    let mut log = ChangeLog::new(4);
+    for path in ["a", "b", "a", "c"] {
+        log.record(BString::from(path));
+    }
+    assert_eq!(paths(&log, 0), Some(vec!["a", "b", "c"]));
+    assert_eq!(paths(&log, 2), Some(vec!["a", "c"]));
+    assert_eq!(paths(&log, 4), Some(vec![]));
+    assert_eq!(paths(&log, 5), None);
+
+    log.record(BString::from("d"));
+    assert_eq!(log.seq(), 5);
+    assert_eq!(paths(&log, 0), None);
+    assert_eq!(paths(&log, 2), None);
+    assert_eq!(paths(&log, 3), Some(vec!["c", "d"]));
+
+    log.forget();
+    assert_eq!(paths(&log, 5), None);
+    assert_eq!(paths(&log, log.seq()), Some(vec![]));
+}
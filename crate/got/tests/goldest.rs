@@ -0,0 +1,87 @@
+//! `got goldest` against the repository it runs in
+
+mod common;
+
+use common::Repo;
+
+fn has_untracked_cache(repo: &Repo) -> bool {
+    let index = std::fs::read(repo.file(".git/index")).unwrap();
+    index.windows(4).any(|window| window == b"UNTR")
+}
+
+/// A bare `-u` walks in the configured mode, so the untracked cache, which is
+/// only kept for that mode, gets used and written
+#[test]
+fn bare_untracked_flag_keeps_the_untracked_cache() {
+    for (configured, shown) in [(None, "dir/"), (Some("all"), "dir/file")] {
+        let repo = Repo::init();
+        repo.git(&["config", "core.untrackedCache", "true"]);
+        if let Some(mode) = configured {
+            repo.git(&["config", "status.showUntrackedFiles", mode]);
+        }
+        repo.write("tracked", "1\n");
+        repo.commit_all("initial");
+        repo.write("dir/file", "?\n");
+
+        let output = repo.got(&["goldest", "-u", "-f"]).output().unwrap();
+        assert!(output.status.success(), "{configured:?}");
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(
+            stdout.lines().collect::<Vec<_>>(),
+            [shown],
+            "{configured:?}"
+        );
+        assert!(has_untracked_cache(&repo), "{configured:?}");
+    }
+}
+
+/// `--all-worktrees` adds the changes of linked checkouts under their full
+/// path, and passes over ones whose directory is gone
+#[test]
+fn all_worktrees_prefixes_linked_checkouts_and_skips_prunable_ones() {
+    let repo = Repo::init();
+    repo.write("tracked", "1\n");
+    repo.commit_all("initial");
+    let linked = tempfile::tempdir().unwrap();
+    let linked = linked.path().canonicalize().unwrap();
+    let (checkout, gone) = (linked.join("checkout"), linked.join("gone"));
+    for path in [&checkout, &gone] {
+        repo.git(&["worktree", "add", "-q", "--detach", path.to_str().unwrap()]);
+    }
+    std::fs::write(checkout.join("tracked"), "2\n").unwrap();
+    std::fs::remove_dir_all(&gone).unwrap();
+    repo.write("tracked", "2\n");
+
+    let worktrees = gixkit::list_worktrees(&gixkit::open_repo(repo.path()).unwrap()).unwrap();
+    let prunable: Vec<_> = worktrees
+        .iter()
+        .map(|worktree| (worktree.path.canonicalize().ok(), worktree.prunable))
+        .collect();
+    assert_eq!(
+        prunable,
+        [
+            (Some(repo.path().canonicalize().unwrap()), false),
+            (Some(checkout.clone()), false),
+            (None, true)
+        ]
+    );
+
+    let output = repo
+        .got(&["goldest", "--all-worktrees", "-f", "-l", "10"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let mut lines: Vec<String> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(str::to_owned)
+        .collect();
+    lines.sort();
+    assert_eq!(
+        lines,
+        [
+            format!("{}/tracked", checkout.display()),
+            "tracked".to_owned()
+        ]
+    );
+}
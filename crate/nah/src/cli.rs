@@ -57,6 +57,10 @@ pub enum NahCommands {
         /// Add to global ignore list
         #[arg(short, long)]
         global: bool,
+
+        /// Limit to untracked files matching these pathspecs
+        #[arg(value_name = "PATHSPEC")]
+        pathspec: Vec<std::ffi::OsString>,
     },
 }
 
@@ -66,7 +70,7 @@ pub fn execute(cmd: NahCommands) -> Result<()> {
         NahCommands::Remove { pattern, global } => execute_remove(&pattern, global),
         NahCommands::List { global } => execute_list(global),
         NahCommands::Show { global } => execute_show(global),
-        NahCommands::Pick { global } => execute_pick(global),
+        NahCommands::Pick { global, pathspec } => execute_pick(global, &pathspec),
     }
 }
 
@@ -113,15 +117,16 @@ fn execute_show(global: bool) -> Result<()> {
     Ok(())
 }
 
-fn execute_pick(global: bool) -> Result<()> {
-    let files = pick::get_untracked_files()?;
+fn execute_pick(global: bool, pathspec: &[std::ffi::OsString]) -> Result<()> {
+    let files = pick::get_untracked_files(pathspec)?;
 
     if files.is_empty() {
         println!("No untracked files to ignore.");
         return Ok(());
     }
 
-    pick::display_file_selection(&files)?;
+    let prefix = gixkit::cwd_prefix(&gixkit::discover_repo(std::env::current_dir()?)?);
+    pick::display_file_selection(&files, prefix.as_ref().map(|p| p.as_ref()))?;
     println!();
     println!("Enter selection (or 'q' to cancel): ");
 
@@ -143,9 +148,9 @@ fn execute_pick(global: bool) -> Result<()> {
 
     println!();
     for index in selected {
-        let pattern = &files[index - 1];
-        ignore::add_pattern(pattern, global, None)?;
-        println!("Ignored: {}", pattern);
+        let path = &files[index - 1];
+        ignore::add_pattern(ignore::path_pattern(path), global, None)?;
+        println!("Ignored: {}", gixkit::quote_path(path.as_ref(), true));
     }
 
     Ok(())
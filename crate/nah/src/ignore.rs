@@ -8,11 +8,17 @@ pub fn get_nah_path(global: bool, repo_path: Option<&Path>) -> Result<PathBuf> {
     if global {
         Ok(xdg_git_nah())
     } else {
-        let repo = repo_path
-            .map(Path::to_path_buf)
-            .or_else(|| std::env::current_dir().ok())
-            .context("Cannot determine repository path")?;
-        Ok(repo.join(".git").join("nah"))
+        let dir = match repo_path {
+            Some(path) => path.to_path_buf(),
+            None => std::env::current_dir().context("Cannot determine repository path")?,
+        };
+        // shared by all worktrees of the repository, wherever its git dir is
+        let repo = gixkit::discover_repo(dir)?;
+        let common_dir = repo.common_dir();
+        let common_dir = common_dir
+            .canonicalize()
+            .unwrap_or_else(|_| common_dir.to_path_buf());
+        Ok(common_dir.join("nah"))
     }
 }
 
@@ -33,7 +39,29 @@ pub fn read_patterns(global: bool, repo_path: Option<&Path>) -> Result<Vec<Strin
         .collect())
 }
 
-pub fn add_pattern(pattern: &str, global: bool, repo_path: Option<&Path>) -> Result<()> {
+/// The pattern that matches just `path`, relative to the worktree root: anchored
+/// with a leading `/`, with glob and comment characters and trailing spaces
+/// backslash-escaped
+pub fn path_pattern(path: &[u8]) -> Vec<u8> {
+    let mut pattern = Vec::with_capacity(path.len() + 1);
+    pattern.push(b'/');
+    // trailing spaces would be stripped from the pattern
+    let spaces_from = path.iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+    for (i, &byte) in path.iter().enumerate() {
+        if matches!(byte, b'*' | b'?' | b'[' | b'!' | b'#' | b'\\') || i >= spaces_from {
+            pattern.push(b'\\');
+        }
+        pattern.push(byte);
+    }
+    pattern
+}
+
+/// Append `pattern`, which may be a raw, non-UTF-8 path
+pub fn add_pattern(
+    pattern: impl AsRef<[u8]>,
+    global: bool,
+    repo_path: Option<&Path>,
+) -> Result<()> {
     let path = get_nah_path(global, repo_path)?;
 
     if let Some(parent) = path.parent() {
@@ -47,7 +75,8 @@ pub fn add_pattern(pattern: &str, global: bool, repo_path: Option<&Path>) -> Res
         .open(&path)
         .with_context(|| format!("Failed to open nah file: {}", path.display()))?;
 
-    writeln!(file, "{}", pattern)
+    file.write_all(pattern.as_ref())
+        .and_then(|()| file.write_all(b"\n"))
         .with_context(|| format!("Failed to write pattern to nah file: {}", path.display()))?;
 
     Ok(())
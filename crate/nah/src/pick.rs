@@ -1,10 +1,25 @@
 use anyhow::Result;
+use gixkit::{discover_repo, quote_path, IterMode, RepoIterBuilder, UntrackedFilter};
+use gixkit::{relative_path, BStr, BString};
+use std::sync::Arc;
 
-pub fn get_untracked_files() -> Result<Vec<String>> {
-    Err(anyhow::anyhow!("get_untracked_files not implemented yet"))
+pub fn get_untracked_files(pathspec: &[std::ffi::OsString]) -> Result<Vec<BString>> {
+    let repo = discover_repo(std::env::current_dir()?)?;
+    #[allow(clippy::arc_with_non_send_sync)]
+    let repo = Arc::new(repo);
+
+    Ok(RepoIterBuilder::new(repo)
+        .mode(IterMode::Untracked)
+        // a wholly untracked directory is offered as a single `dir/` pattern
+        .untracked(UntrackedFilter::Normal)
+        .pathspec(pathspec.iter().map(|p| p.as_encoded_bytes()))
+        .build()?
+        .map(|result| result.map(|status| status.path))
+        .collect::<gixkit::Result<_>>()?)
 }
 
-pub fn display_file_selection(files: &[String]) -> Result<()> {
+/// List `files` for picking, relative to `prefix` (see [`gixkit::cwd_prefix`])
+pub fn display_file_selection(files: &[BString], prefix: Option<&BStr>) -> Result<()> {
     if files.is_empty() {
         println!("No untracked files found.");
         return Ok(());
@@ -14,7 +29,11 @@ pub fn display_file_selection(files: &[String]) -> Result<()> {
     println!();
 
     for (i, file) in files.iter().enumerate() {
-        println!("  [{}] {}", i + 1, file);
+        let file = match prefix {
+            Some(prefix) => relative_path(file.as_ref(), prefix),
+            None => file.clone(),
+        };
+        println!("  [{}] {}", i + 1, quote_path(file.as_ref(), true));
     }
 
     Ok(())
@@ -1,23 +1,58 @@
 use anyhow::Result;
-use gixkit::{open_repo, IterMode, RepoIterBuilder};
+use gixkit::{discover_repo, quote_non_ascii, IgnoredFilter, IterMode, RepoIterBuilder};
+use std::ffi::OsString;
+use std::io::Write;
 use std::sync::Arc;
 
 fn main() -> Result<()> {
-    let repo = open_repo(".")?;
+    let repo = discover_repo(std::env::current_dir()?)?;
+    #[allow(clippy::arc_with_non_send_sync)]
     let repo = Arc::new(repo);
 
+    // porcelain [-z] [--ignored[=<mode>]] [--] [<pathspec>...]
+    let mut args = std::env::args_os().skip(1).peekable();
+    let mut null_terminated = false;
+    let mut ignored = IgnoredFilter::No;
+    while let Some(arg) = args.next_if(|arg| arg.as_encoded_bytes().starts_with(b"-")) {
+        match arg.to_str() {
+            Some("--") => break,
+            Some("-z") => null_terminated = true,
+            Some("--ignored") => ignored = IgnoredFilter::Traditional,
+            Some(option) if option.starts_with("--ignored=") => {
+                let value = &option["--ignored=".len()..];
+                ignored = IgnoredFilter::from_arg(value)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid ignored mode '{}'", value))?;
+            }
+            _ => anyhow::bail!("Unknown option {:?}", arg),
+        }
+    }
+    let pathspec: Vec<Vec<u8>> = args.map(OsString::into_encoded_bytes).collect();
+
     let iter = RepoIterBuilder::new(Arc::clone(&repo))
         .mode(IterMode::Both)
+        .ignored(ignored)
+        .pathspec(&pathspec)
+        .parallel(0)
         .build()?;
 
+    let quote_non_ascii = quote_non_ascii(&repo);
+    let mut out = std::io::stdout().lock();
     for result in iter {
         let status = result?;
-        println!(
-            "{}{} {}",
-            char::from(status.index_status),
-            char::from(status.worktree_status),
-            status.path
-        );
+        let (x, y) = status.xy();
+        write!(out, "{}{} ", char::from(x), char::from(y))?;
+        if null_terminated {
+            // `XY new\0old\0` for renames, raw paths throughout
+            out.write_all(&status.path)?;
+            out.write_all(b"\0")?;
+            if let Some(ref orig_path) = status.orig_path {
+                out.write_all(orig_path)?;
+                out.write_all(b"\0")?;
+            }
+        } else {
+            out.write_all(&status.display_path(quote_non_ascii))?;
+            out.write_all(b"\n")?;
+        }
     }
 
     Ok(())